@@ -1,4 +1,4 @@
-use crate::types::{Side, Token};
+use crate::types::{BatchOp, MarketAmount, OrderOptions, Side, StopKind, TimeInForce, Trigger};
 
 use super::minidex::*;
 use erc20::*;
@@ -19,6 +19,17 @@ fn assert_event<T: Decode, E: Environment, F>(
     verify_fn(&event);
 }
 
+// decode only events of type `T`, told apart by their signature topic
+fn decode_event<T: Decode + ink::env::Event, E: Environment>(
+    contract_event: &EventWithTopics<ContractEmitted<E>>,
+) -> Option<T> {
+    let topic = contract_event.topics.first()?;
+    if Some(topic.0) != T::SIGNATURE_TOPIC {
+        return None;
+    }
+    T::decode(&mut &contract_event.event.data[..]).ok()
+}
+
 // avoid annoying types
 macro_rules! setup_contracts {
     ($client:expr) => {{
@@ -42,13 +53,21 @@ macro_rules! setup_contracts {
         let quote_call_builder = quote.call_builder::<Erc20>();
 
         // init dex contract
-        let mut dex_constructor = MiniDexRef::new(base.account_id, quote.account_id);
+        let mut dex_constructor = MiniDexRef::new();
         let dex = $client
             .instantiate("minidex", &ink_e2e::charlie(), &mut dex_constructor)
             .submit()
             .await
             .expect("instantiate failed");
-        let dex_call_builder = dex.call_builder::<MiniDex>();
+        let mut dex_call_builder = dex.call_builder::<MiniDex>();
+
+        // list (base, quote) market
+        let add_market = dex_call_builder.add_market(base.account_id, quote.account_id);
+        $client
+            .call(&ink_e2e::charlie(), &add_market)
+            .submit()
+            .await
+            .expect("add market failed");
 
         (
             base,
@@ -64,7 +83,7 @@ macro_rules! setup_contracts {
 #[ink_e2e::test]
 async fn test_deposit_and_withdraw<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
     // given
-    let (_base, _quote, dex, mut base_call_builder, _, mut dex_call_builder) =
+    let (base, _quote, dex, mut base_call_builder, _, mut dex_call_builder) =
         setup_contracts!(client);
 
     // init tokens
@@ -84,7 +103,7 @@ async fn test_deposit_and_withdraw<Client: ContractsBackend>(mut client: Client)
 
     // when
     let deposit_amount = 100_000u128;
-    let deposit = dex_call_builder.deposit(Token::Base, deposit_amount);
+    let deposit = dex_call_builder.deposit(base.account_id, deposit_amount);
     let deposit_result = client.call(&ink_e2e::dave(), &deposit).submit().await;
 
     // then
@@ -104,7 +123,7 @@ async fn test_deposit_and_withdraw<Client: ContractsBackend>(mut client: Client)
 
     // then
     // Now deposit should succeed
-    let deposit = dex_call_builder.deposit(Token::Base, deposit_amount);
+    let deposit = dex_call_builder.deposit(base.account_id, deposit_amount);
     let deposit_result = client.call(&ink_e2e::dave(), &deposit).submit().await;
     assert!(
         deposit_result.is_ok(),
@@ -114,7 +133,7 @@ async fn test_deposit_and_withdraw<Client: ContractsBackend>(mut client: Client)
     assert_eq!(contract_events.len(), 2); // erc20::transfer + minidex::Deposit
     assert_event(&contract_events[1], |event: &Deposit| {
         assert_eq!(event.account, acct);
-        assert_eq!(event.token, Token::Base);
+        assert_eq!(event.token, base.account_id);
         assert_eq!(event.amount, deposit_amount);
     });
 
@@ -129,14 +148,14 @@ async fn test_deposit_and_withdraw<Client: ContractsBackend>(mut client: Client)
 
     // when
     // withdraw tokens
-    let withdraw = dex_call_builder.withdraw(Token::Base, deposit_amount);
+    let withdraw = dex_call_builder.withdraw(base.account_id, deposit_amount);
     let withdraw_result = client.call(&ink_e2e::dave(), &withdraw).submit().await;
     assert!(withdraw_result.is_ok(), "withdraw should succeed");
     let contract_events = withdraw_result.unwrap().contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 2); // erc20::transfer + minidex::Withdraw
     assert_event(&contract_events[1], |event: &Withdraw| {
         assert_eq!(event.account, acct);
-        assert_eq!(event.token, Token::Base);
+        assert_eq!(event.token, base.account_id);
         assert_eq!(event.amount, deposit_amount);
     });
 
@@ -158,7 +177,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     mut client: Client,
 ) -> E2EResult<()> {
     // init contracts
-    let (_base, _quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
         setup_contracts!(client);
 
     // user accounts
@@ -193,7 +212,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
         .await?;
 
     // seller1 deposit base token
-    let deposit_base = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000_000);
     let deposit_result = client
         .call(&ink_e2e::dave(), &deposit_base)
         .submit()
@@ -202,12 +221,12 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     assert_eq!(contract_events.len(), 2); // erc20::transfer + minidex::Deposit
     assert_event(&contract_events[1], |event: &Deposit| {
         assert_eq!(event.account, seller1);
-        assert_eq!(event.token, Token::Base);
+        assert_eq!(event.token, base.account_id);
         assert_eq!(event.amount, 1_000_000);
     });
 
     // buyer deposit quote token
-    let deposit_quote = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 1_000_000);
     let deposit_result = client
         .call(&ink_e2e::ferdie(), &deposit_quote)
         .submit()
@@ -216,77 +235,116 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     assert_eq!(contract_events.len(), 2); // erc20::transfer + minidex::Deposit
     assert_event(&contract_events[1], |event: &Deposit| {
         assert_eq!(event.account, buyer);
-        assert_eq!(event.token, Token::Quote);
+        assert_eq!(event.token, quote.account_id);
         assert_eq!(event.amount, 1_000_000);
     });
 
     // seller1 create 2 sell orders
     // sell_order1: price 90，qty 100
-    let sell_order1 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 90, 100);
+    let sell_order1 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        90,
+        100,
+        OrderOptions::default(),
+    );
     let sell_result1 = client.call(&ink_e2e::dave(), &sell_order1).submit().await?;
     let contract_events = sell_result1.contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 1); // minidex::NewOrder
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 90);
-        assert_eq!(event.qty, 100);
+        assert_eq!(event.price, Some(90));
+        assert_eq!(event.amount, MarketAmount::Base(100));
     });
     let order_id1 = sell_result1.return_value().expect("place sell_order1");
     println!("seller1 created: {order_id1}");
 
     // sell_order2: price 100，qty 100
-    let sell_order2 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 100, 100);
+    let sell_order2 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        100,
+        100,
+        OrderOptions::default(),
+    );
     let sell_result2 = client.call(&ink_e2e::dave(), &sell_order2).submit().await?;
     let contract_events = sell_result2.contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 1); // minidex::NewOrder
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 100);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(100));
     });
     let order_id2 = sell_result2.return_value().expect("place sell_order2");
     println!("seller1 created: {order_id2}");
 
     // buyer order：price 100，qty 150
     // this buy order will match sell_order1 completely and sell_order2 partially
-    let buy_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 100, 150);
+    let buy_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        100,
+        150,
+        OrderOptions::default(),
+    );
     let buy_result = client.call(&ink_e2e::ferdie(), &buy_order).submit().await?;
     let contract_events = buy_result.contract_emitted_events().unwrap();
     let buy_order_id = buy_result.return_value().expect("place buy_order");
     println!("buyer created: {buy_order_id}");
-    assert_eq!(contract_events.len(), 5); // minidex::NewOrder + 4 minidex::OrderFilled
+    // minidex::NewOrder + 2 * (2 minidex::OrderFilled + minidex::Trade)
+    assert_eq!(contract_events.len(), 7);
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 150);
+        assert_eq!(event.owner, buyer);
+        assert_eq!(event.pair, (base.account_id, quote.account_id));
+        assert_eq!(event.side, Side::Buy);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(150));
     });
     // sell_order1 filled
     assert_event(&contract_events[1], |event: &OrderFilled| {
         assert_eq!(event.order_id, order_id1);
         assert_eq!(event.filled_price, 90);
         assert_eq!(event.filled_qty, 100);
+        assert_eq!(event.remaining, MarketAmount::Base(0));
     });
     // buy_order filled with sell_order1
     assert_event(&contract_events[2], |event: &OrderFilled| {
         assert_eq!(event.order_id, buy_order_id);
         assert_eq!(event.filled_price, 90);
         assert_eq!(event.filled_qty, 100);
+        assert_eq!(event.remaining, MarketAmount::Base(50));
+    });
+    assert_event(&contract_events[3], |event: &Trade| {
+        assert_eq!(event.maker_order_id, order_id1);
+        assert_eq!(event.taker_order_id, buy_order_id);
+        assert_eq!((event.maker, event.taker), (seller1, buyer));
+        assert_eq!(event.taker_side, Side::Buy);
+        assert_eq!(event.price, 90);
+        assert_eq!(event.qty, 100);
+        assert_eq!(event.quote_amount, 9000);
     });
     // sell_order2 filled
-    assert_event(&contract_events[3], |event: &OrderFilled| {
+    assert_event(&contract_events[4], |event: &OrderFilled| {
         assert_eq!(event.order_id, order_id2);
         assert_eq!(event.filled_price, 100);
         assert_eq!(event.filled_qty, 50);
+        assert_eq!(event.remaining, MarketAmount::Base(50));
     });
     // buy_order filled with sell_order2
-    assert_event(&contract_events[4], |event: &OrderFilled| {
+    assert_event(&contract_events[5], |event: &OrderFilled| {
         assert_eq!(event.order_id, buy_order_id);
         assert_eq!(event.filled_price, 100);
         assert_eq!(event.filled_qty, 50);
+        assert_eq!(event.remaining, MarketAmount::Base(0));
+    });
+    assert_event(&contract_events[6], |event: &Trade| {
+        assert_eq!(event.maker_order_id, order_id2);
+        assert_eq!(event.taker_order_id, buy_order_id);
+        assert_eq!(event.price, 100);
+        assert_eq!(event.qty, 50);
+        assert_eq!(event.quote_amount, 5000);
     });
 
     // verify balance changes
-    let buyer_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer_base_result = client
         .call(&ink_e2e::ferdie(), &buyer_base_balance)
         .submit()
@@ -297,7 +355,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
         "buyer should have 150 base tokens in vault"
     );
 
-    let buyer_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let buyer_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let buyer_quote_result = client
         .call(&ink_e2e::ferdie(), &buyer_quote_balance)
         .submit()
@@ -309,7 +367,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     );
 
     // check buyer's quote token locked amount
-    let buyer_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let buyer_quote_locked_result = client
         .call(&ink_e2e::ferdie(), &buyer_quote_locked)
         .submit()
@@ -321,7 +379,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     );
 
     // check seller1's quote token balance in vault
-    let seller1_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let seller1_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let seller1_quote_result = client
         .call(&ink_e2e::dave(), &seller1_quote_balance)
         .submit()
@@ -333,7 +391,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     );
 
     // check seller1's base token locked amount
-    let seller1_base_locked = dex_call_builder.locked_of(Token::Base);
+    let seller1_base_locked = dex_call_builder.locked_of(base.account_id);
     let seller1_base_locked_result = client
         .call(&ink_e2e::dave(), &seller1_base_locked)
         .submit()
@@ -345,7 +403,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     );
 
     // check seller2's base token balance in vault
-    let seller2_base_balance = dex_call_builder.balance_of(Token::Base);
+    let seller2_base_balance = dex_call_builder.balance_of(base.account_id);
     let seller2_base_result = client
         .call(&ink_e2e::eve(), &seller2_base_balance)
         .submit()
@@ -357,7 +415,7 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     );
 
     // check seller2's quote token locked amount
-    let seller2_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let seller2_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let seller2_quote_locked_result = client
         .call(&ink_e2e::eve(), &seller2_quote_locked)
         .submit()
@@ -369,14 +427,14 @@ async fn test_buy_order_matches_multiple_sell_orders<Client: ContractsBackend>(
     );
 
     // buyer withdraw base tokens
-    let withdraw_base = dex_call_builder.withdraw(Token::Base, 150);
+    let withdraw_base = dex_call_builder.withdraw(base.account_id, 150);
     client
         .call(&ink_e2e::ferdie(), &withdraw_base)
         .submit()
         .await?;
 
     // check buyer's base token balance in vault after withdrawal
-    let buyer_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer_base_result = client
         .call(&ink_e2e::ferdie(), &buyer_base_balance)
         .submit()
@@ -407,7 +465,7 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
     mut client: Client,
 ) -> E2EResult<()> {
     // init contracts
-    let (_base, _quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
         setup_contracts!(client);
 
     // user accounts
@@ -454,93 +512,127 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
         .await?;
 
     // buyer1 and buyer2 deposit quote token
-    let deposit_quote1 = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote1 = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::dave(), &deposit_quote1)
         .submit()
         .await?;
 
-    let deposit_quote2 = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote2 = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::eve(), &deposit_quote2)
         .submit()
         .await?;
 
     // seller deposit base token
-    let deposit_base = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000_000);
     client
         .call(&ink_e2e::ferdie(), &deposit_base)
         .submit()
         .await?;
 
     // buyer1 create buy order: price 110, qty 100
-    let buy_order1 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 110, 100);
+    let buy_order1 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        110,
+        100,
+        OrderOptions::default(),
+    );
     let buy_result1 = client.call(&ink_e2e::dave(), &buy_order1).submit().await?;
     let contract_events = buy_result1.contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 1); // minidex::NewOrder
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 110);
-        assert_eq!(event.qty, 100);
+        assert_eq!(event.price, Some(110));
+        assert_eq!(event.amount, MarketAmount::Base(100));
     });
     let buy_order_id1 = buy_result1.return_value().expect("place buy_order1");
     println!("buyer1 created: {buy_order_id1}");
 
     // buyer2 create buy order: price 100, qty 100
-    let buy_order2 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 100, 100);
+    let buy_order2 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        100,
+        100,
+        OrderOptions::default(),
+    );
     let buy_result2 = client.call(&ink_e2e::eve(), &buy_order2).submit().await?;
     let contract_events = buy_result2.contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 1); // minidex::NewOrder
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 100);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(100));
     });
     let buy_order_id2 = buy_result2.return_value().expect("place buy_order2");
     println!("buyer2 created: {buy_order_id2}");
 
     // seller create sell order: price 100, qty 150
     // this sell order will match buy_order1 completely and buy_order2 partially
-    let sell_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 100, 150);
+    let sell_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        100,
+        150,
+        OrderOptions::default(),
+    );
     let sell_result = client
         .call(&ink_e2e::ferdie(), &sell_order)
         .submit()
         .await?;
     let contract_events = sell_result.contract_emitted_events().unwrap();
     let sell_order_id = sell_result.return_value().expect("place sell order");
-    assert_eq!(contract_events.len(), 5); // minidex::NewOrder + 4 minidex::OrderFilled
+    // minidex::NewOrder + 2 * (2 minidex::OrderFilled + minidex::Trade)
+    assert_eq!(contract_events.len(), 7);
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 150);
+        assert_eq!(event.owner, seller);
+        assert_eq!(event.side, Side::Sell);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(150));
     });
-    // buy_order1 filled
+    // buy_order1 filled at its own price
     assert_event(&contract_events[1], |event: &OrderFilled| {
         assert_eq!(event.order_id, buy_order_id1);
-        assert_eq!(event.filled_price, 100);
+        assert_eq!(event.filled_price, 110);
         assert_eq!(event.filled_qty, 100);
+        assert_eq!(event.remaining, MarketAmount::Base(0));
     });
     // sell order filled with buy_order1
     assert_event(&contract_events[2], |event: &OrderFilled| {
         assert_eq!(event.order_id, sell_order_id);
-        assert_eq!(event.filled_price, 100);
+        assert_eq!(event.filled_price, 110);
         assert_eq!(event.filled_qty, 100);
+        assert_eq!(event.remaining, MarketAmount::Base(50));
+    });
+    assert_event(&contract_events[3], |event: &Trade| {
+        assert_eq!(event.maker_order_id, buy_order_id1);
+        assert_eq!(event.taker_order_id, sell_order_id);
+        assert_eq!((event.maker, event.taker), (buyer1, seller));
+        assert_eq!(event.taker_side, Side::Sell);
+        assert_eq!(event.quote_amount, 11000);
     });
     // buy_order2 filled
-    assert_event(&contract_events[3], |event: &OrderFilled| {
+    assert_event(&contract_events[4], |event: &OrderFilled| {
         assert_eq!(event.order_id, buy_order_id2);
         assert_eq!(event.filled_price, 100);
         assert_eq!(event.filled_qty, 50);
+        assert_eq!(event.remaining, MarketAmount::Base(50));
     });
     // sell order filled with buy_order2
-    assert_event(&contract_events[4], |event: &OrderFilled| {
+    assert_event(&contract_events[5], |event: &OrderFilled| {
         assert_eq!(event.order_id, sell_order_id);
         assert_eq!(event.filled_price, 100);
         assert_eq!(event.filled_qty, 50);
+        assert_eq!(event.remaining, MarketAmount::Base(0));
+    });
+    assert_event(&contract_events[6], |event: &Trade| {
+        assert_eq!((event.maker, event.taker), (buyer2, seller));
+        assert_eq!(event.qty, 50);
+        assert_eq!(event.quote_amount, 5000);
     });
 
     // verify balance changes
-    let buyer1_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer1_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer1_base_result = client
         .call(&ink_e2e::dave(), &buyer1_base_balance)
         .submit()
@@ -551,7 +643,7 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
         "buyer1 should have 100 base tokens in vault"
     );
 
-    let buyer2_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer2_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer2_base_result = client
         .call(&ink_e2e::eve(), &buyer2_base_balance)
         .submit()
@@ -562,19 +654,19 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
         "buyer2 should have 50 base tokens in vault"
     );
 
-    let seller_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let seller_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let seller_quote_result = client
         .call(&ink_e2e::ferdie(), &seller_quote_balance)
         .submit()
         .await?;
     assert_eq!(
         seller_quote_result.return_value(),
-        15000,
-        "seller should have 15000 quote tokens in vault (100 * 100 + 100 * 50)"
+        16000,
+        "seller should have 16000 quote tokens in vault (110 * 100 + 100 * 50)"
     );
 
     // check buyer1's quote token locked amount
-    let buyer1_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let buyer1_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let buyer1_quote_locked_result = client
         .call(&ink_e2e::dave(), &buyer1_quote_locked)
         .submit()
@@ -586,7 +678,7 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
     );
 
     // check buyer2's quote token locked amount
-    let buyer2_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let buyer2_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let buyer2_quote_locked_result = client
         .call(&ink_e2e::eve(), &buyer2_quote_locked)
         .submit()
@@ -598,7 +690,7 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
     );
 
     // check seller's base token locked amount
-    let seller_base_locked = dex_call_builder.locked_of(Token::Base);
+    let seller_base_locked = dex_call_builder.locked_of(base.account_id);
     let seller_base_locked_result = client
         .call(&ink_e2e::ferdie(), &seller_base_locked)
         .submit()
@@ -615,7 +707,7 @@ async fn test_sell_order_matches_multiple_buy_orders<Client: ContractsBackend>(
 #[ink_e2e::test]
 async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
     // init contracts
-    let (_base, _quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
         setup_contracts!(client);
 
     // user accounts
@@ -646,38 +738,49 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
     client.call(&ink_e2e::eve(), &approve_base).submit().await?;
 
     // buyer deposit quote token
-    let deposit_quote = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::dave(), &deposit_quote)
         .submit()
         .await?;
 
     // seller deposit base token
-    let deposit_base = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000_000);
     client.call(&ink_e2e::eve(), &deposit_base).submit().await?;
 
     // buyer create buy order: price 100, qty 100
-    let buy_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 100, 100);
+    let buy_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        100,
+        100,
+        OrderOptions::default(),
+    );
     let buy_result = client.call(&ink_e2e::dave(), &buy_order).submit().await?;
     let contract_events = buy_result.contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 1); // minidex::NewOrder
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 100);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(100));
     });
     let buy_order_id = buy_result.return_value().expect("place buy order");
     println!("buyer created: {buy_order_id}");
 
     // seller create sell order: price 100, qty 50 to partially fill the buy order
-    let sell_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 100, 50);
+    let sell_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        100,
+        50,
+        OrderOptions::default(),
+    );
     let sell_result = client.call(&ink_e2e::eve(), &sell_order).submit().await?;
     let contract_events = sell_result.contract_emitted_events().unwrap();
-    assert_eq!(contract_events.len(), 3); // minidex::NewOrder + 2 minidex::OrderFilled
+    // minidex::NewOrder + 2 minidex::OrderFilled + minidex::Trade
+    assert_eq!(contract_events.len(), 4);
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 50);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(50));
     });
     // buy order filled
     assert_event(&contract_events[1], |event: &OrderFilled| {
@@ -696,7 +799,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
     });
 
     // check balances after partial fill
-    let buyer_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer_base_result = client
         .call(&ink_e2e::dave(), &buyer_base_balance)
         .submit()
@@ -707,7 +810,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "buyer should have 50 base tokens in vault after partial fill"
     );
 
-    let buyer_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let buyer_quote_locked_result = client
         .call(&ink_e2e::dave(), &buyer_quote_locked)
         .submit()
@@ -718,7 +821,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "buyer should have 5000 quote tokens locked (remaining 50 tokens at price 100)"
     );
 
-    let seller_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let seller_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let seller_quote_result = client
         .call(&ink_e2e::eve(), &seller_quote_balance)
         .submit()
@@ -742,7 +845,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
     });
 
     // verify balance changes after cancellation
-    let buyer_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let buyer_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let buyer_quote_result = client
         .call(&ink_e2e::dave(), &buyer_quote_balance)
         .submit()
@@ -753,7 +856,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "buyer should have 995000 quote tokens in vault (1000000 - 5000 for filled part)"
     );
 
-    let buyer_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let buyer_quote_locked_result = client
         .call(&ink_e2e::dave(), &buyer_quote_locked)
         .submit()
@@ -764,7 +867,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "buyer should have no quote tokens locked after cancellation"
     );
 
-    let buyer_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer_base_result = client
         .call(&ink_e2e::dave(), &buyer_base_balance)
         .submit()
@@ -781,7 +884,7 @@ async fn test_buy_order_cancel<Client: ContractsBackend>(mut client: Client) ->
 #[ink_e2e::test]
 async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
     // init contracts
-    let (_base, _quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
         setup_contracts!(client);
 
     // user accounts
@@ -812,38 +915,49 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
     client.call(&ink_e2e::eve(), &approve_base).submit().await?;
 
     // buyer deposit quote token
-    let deposit_quote = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::dave(), &deposit_quote)
         .submit()
         .await?;
 
     // seller deposit base token
-    let deposit_base = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000_000);
     client.call(&ink_e2e::eve(), &deposit_base).submit().await?;
 
     // seller create sell order: price 100, qty 100
-    let sell_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 100, 100);
+    let sell_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        100,
+        100,
+        OrderOptions::default(),
+    );
     let sell_result = client.call(&ink_e2e::eve(), &sell_order).submit().await?;
     let contract_events = sell_result.contract_emitted_events().unwrap();
     assert_eq!(contract_events.len(), 1); // minidex::NewOrder
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 100);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(100));
     });
     let sell_order_id = sell_result.return_value().expect("place sell order");
     println!("seller created: {sell_order_id}");
 
     // buyer create buy order: price 100, qty 50 to partially fill the sell order
-    let buy_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 100, 50);
+    let buy_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        100,
+        50,
+        OrderOptions::default(),
+    );
     let buy_result = client.call(&ink_e2e::dave(), &buy_order).submit().await?;
     let contract_events = buy_result.contract_emitted_events().unwrap();
-    assert_eq!(contract_events.len(), 3); // minidex::NewOrder + 2 minidex::OrderFilled
+    // minidex::NewOrder + 2 minidex::OrderFilled + minidex::Trade
+    assert_eq!(contract_events.len(), 4);
     assert_event(&contract_events[0], |event: &NewOrder| {
-        assert_eq!(event.price, 100);
-        assert_eq!(event.qty, 50);
+        assert_eq!(event.price, Some(100));
+        assert_eq!(event.amount, MarketAmount::Base(50));
     });
     // sell order filled
     assert_event(&contract_events[1], |event: &OrderFilled| {
@@ -862,7 +976,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
     });
 
     // check balances after partial fill
-    let buyer_base_balance = dex_call_builder.balance_of(Token::Base);
+    let buyer_base_balance = dex_call_builder.balance_of(base.account_id);
     let buyer_base_result = client
         .call(&ink_e2e::dave(), &buyer_base_balance)
         .submit()
@@ -873,7 +987,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "buyer should have 50 base tokens in vault after partial fill"
     );
 
-    let buyer_quote_locked = dex_call_builder.locked_of(Token::Quote);
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
     let buyer_quote_locked_result = client
         .call(&ink_e2e::dave(), &buyer_quote_locked)
         .submit()
@@ -884,7 +998,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "buyer should have no quote tokens locked after partial fill"
     );
 
-    let seller_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let seller_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let seller_quote_result = client
         .call(&ink_e2e::eve(), &seller_quote_balance)
         .submit()
@@ -895,7 +1009,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "seller should have 5000 quote tokens in vault (50 * 100)"
     );
 
-    let seller_base_locked = dex_call_builder.locked_of(Token::Base);
+    let seller_base_locked = dex_call_builder.locked_of(base.account_id);
     let seller_base_locked_result = client
         .call(&ink_e2e::eve(), &seller_base_locked)
         .submit()
@@ -916,7 +1030,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
     });
 
     // verify balance changes after cancellation
-    let seller_base_balance = dex_call_builder.balance_of(Token::Base);
+    let seller_base_balance = dex_call_builder.balance_of(base.account_id);
     let seller_base_result = client
         .call(&ink_e2e::eve(), &seller_base_balance)
         .submit()
@@ -927,7 +1041,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "seller should have 999950 base tokens in vault (1000000 - 50 for filled part)"
     );
 
-    let seller_base_locked = dex_call_builder.locked_of(Token::Base);
+    let seller_base_locked = dex_call_builder.locked_of(base.account_id);
     let seller_base_locked_result = client
         .call(&ink_e2e::eve(), &seller_base_locked)
         .submit()
@@ -938,7 +1052,7 @@ async fn test_sell_order_cancel<Client: ContractsBackend>(mut client: Client) ->
         "seller should have no base tokens locked after cancellation"
     );
 
-    let seller_quote_balance = dex_call_builder.balance_of(Token::Quote);
+    let seller_quote_balance = dex_call_builder.balance_of(quote.account_id);
     let seller_quote_result = client
         .call(&ink_e2e::eve(), &seller_quote_balance)
         .submit()
@@ -957,7 +1071,7 @@ async fn test_sell_order_price_time_priority<Client: ContractsBackend>(
     mut client: Client,
 ) -> E2EResult<()> {
     // init contracts
-    let (_base, _quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
         setup_contracts!(client);
 
     // user accounts
@@ -1017,25 +1131,25 @@ async fn test_sell_order_price_time_priority<Client: ContractsBackend>(
         .await?;
 
     // all users deposit tokens
-    let deposit_base1 = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base1 = dex_call_builder.deposit(base.account_id, 1_000_000);
     client
         .call(&ink_e2e::dave(), &deposit_base1)
         .submit()
         .await?;
 
-    let deposit_base2 = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base2 = dex_call_builder.deposit(base.account_id, 1_000_000);
     client
         .call(&ink_e2e::eve(), &deposit_base2)
         .submit()
         .await?;
 
-    let deposit_base3 = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base3 = dex_call_builder.deposit(base.account_id, 1_000_000);
     client
         .call(&ink_e2e::ferdie(), &deposit_base3)
         .submit()
         .await?;
 
-    let deposit_quote = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::charlie(), &deposit_quote)
         .submit()
@@ -1043,20 +1157,35 @@ async fn test_sell_order_price_time_priority<Client: ContractsBackend>(
 
     // Create sell orders in sequence
     // seller1: price 90, qty 20
-    let sell_order1 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 90, 20);
+    let sell_order1 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        90,
+        20,
+        OrderOptions::default(),
+    );
     let sell_result1 = client.call(&ink_e2e::dave(), &sell_order1).submit().await?;
     let sell_order_id1 = sell_result1.return_value().expect("place sell_order1");
 
     // seller2: price 90, qty 20 (same price as seller1, but later)
-    let sell_order2 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 90, 20);
+    let sell_order2 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        90,
+        20,
+        OrderOptions::default(),
+    );
     let sell_result2 = client.call(&ink_e2e::eve(), &sell_order2).submit().await?;
     let sell_order_id2 = sell_result2.return_value().expect("place sell_order2");
 
     // seller3: price 85, qty 20 (better price than seller1 and seller2)
-    let sell_order3 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 85, 20);
+    let sell_order3 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        85,
+        20,
+        OrderOptions::default(),
+    );
     let sell_result3 = client
         .call(&ink_e2e::ferdie(), &sell_order3)
         .submit()
@@ -1064,8 +1193,13 @@ async fn test_sell_order_price_time_priority<Client: ContractsBackend>(
     let sell_order_id3 = sell_result3.return_value().expect("place sell_order3");
 
     // seller4: price 105, qty 20 (higher price than buyer's buy order, should not be matched)
-    let sell_order4 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 105, 20);
+    let sell_order4 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        105,
+        20,
+        OrderOptions::default(),
+    );
     let sell_result4 = client
         .call(&ink_e2e::ferdie(), &sell_order4)
         .submit()
@@ -1077,8 +1211,13 @@ async fn test_sell_order_price_time_priority<Client: ContractsBackend>(
     let expected_sell_order_ids = vec![sell_order_id3, sell_order_id1, sell_order_id2];
 
     // Create a buy order that will match all sell orders
-    let buy_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 100, 60);
+    let buy_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        100,
+        60,
+        OrderOptions::default(),
+    );
     let buy_result = client
         .call(&ink_e2e::charlie(), &buy_order)
         .submit()
@@ -1089,7 +1228,7 @@ async fn test_sell_order_price_time_priority<Client: ContractsBackend>(
     // Extract sell order IDs from OrderFilled events
     let mut matched_sell_order_ids = Vec::new();
     for event in contract_events.iter() {
-        if let Ok(filled_event) = OrderFilled::decode(&mut &event.event.data[..]) {
+        if let Some(filled_event) = decode_event::<OrderFilled, _>(event) {
             if filled_event.order_id != buy_order_id {
                 matched_sell_order_ids.push(filled_event.order_id);
             }
@@ -1110,7 +1249,7 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
     mut client: Client,
 ) -> E2EResult<()> {
     // init contracts
-    let (_base, _quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
         setup_contracts!(client);
 
     // user accounts
@@ -1170,25 +1309,25 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
         .await?;
 
     // all users deposit tokens
-    let deposit_quote1 = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote1 = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::dave(), &deposit_quote1)
         .submit()
         .await?;
 
-    let deposit_quote2 = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote2 = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::eve(), &deposit_quote2)
         .submit()
         .await?;
 
-    let deposit_quote3 = dex_call_builder.deposit(Token::Quote, 1_000_000);
+    let deposit_quote3 = dex_call_builder.deposit(quote.account_id, 1_000_000);
     client
         .call(&ink_e2e::ferdie(), &deposit_quote3)
         .submit()
         .await?;
 
-    let deposit_base = dex_call_builder.deposit(Token::Base, 1_000_000);
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000_000);
     client
         .call(&ink_e2e::charlie(), &deposit_base)
         .submit()
@@ -1196,20 +1335,35 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
 
     // Create buy orders in sequence
     // buyer1: price 110, qty 20
-    let buy_order1 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 110, 20);
+    let buy_order1 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        110,
+        20,
+        OrderOptions::default(),
+    );
     let buy_result1 = client.call(&ink_e2e::dave(), &buy_order1).submit().await?;
     let buy_order_id1 = buy_result1.return_value().expect("place buy_order1");
 
     // buyer2: price 110, qty 20 (same price as buyer1, but later)
-    let buy_order2 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 110, 20);
+    let buy_order2 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        110,
+        20,
+        OrderOptions::default(),
+    );
     let buy_result2 = client.call(&ink_e2e::eve(), &buy_order2).submit().await?;
     let buy_order_id2 = buy_result2.return_value().expect("place buy_order2");
 
     // buyer3: price 115, qty 20 (better price than buyer1 and buyer2)
-    let buy_order3 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 115, 20);
+    let buy_order3 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        115,
+        20,
+        OrderOptions::default(),
+    );
     let buy_result3 = client
         .call(&ink_e2e::ferdie(), &buy_order3)
         .submit()
@@ -1217,8 +1371,13 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
     let buy_order_id3 = buy_result3.return_value().expect("place buy_order3");
 
     // buyer4: price 95, qty 20 (lower price than seller's sell order, should not be matched)
-    let buy_order4 =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Buy, 95, 20);
+    let buy_order4 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        95,
+        20,
+        OrderOptions::default(),
+    );
     let buy_result4 = client.call(&ink_e2e::eve(), &buy_order4).submit().await?;
     let _buy_order_id4 = buy_result4.return_value().expect("place buy_order4");
 
@@ -1227,8 +1386,13 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
     let expected_buy_order_ids = vec![buy_order_id3, buy_order_id1, buy_order_id2];
 
     // Create a sell order that will match all buy orders
-    let sell_order =
-        dex_call_builder.place_limit_order((Token::Base, Token::Quote), Side::Sell, 100, 60);
+    let sell_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        100,
+        60,
+        OrderOptions::default(),
+    );
     let sell_result = client
         .call(&ink_e2e::charlie(), &sell_order)
         .submit()
@@ -1239,7 +1403,7 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
     // Extract buy order IDs from OrderFilled events
     let mut matched_buy_order_ids = Vec::new();
     for event in contract_events.iter() {
-        if let Ok(filled_event) = OrderFilled::decode(&mut &event.event.data[..]) {
+        if let Some(filled_event) = decode_event::<OrderFilled, _>(event) {
             if filled_event.order_id != sell_order_id {
                 matched_buy_order_ids.push(filled_event.order_id);
             }
@@ -1254,3 +1418,715 @@ async fn test_buy_order_price_time_priority<Client: ContractsBackend>(
 
     Ok(())
 }
+
+#[ink_e2e::test]
+async fn test_market_buy_by_quote_amount<Client: ContractsBackend>(
+    mut client: Client,
+) -> E2EResult<()> {
+    // init contracts
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+        setup_contracts!(client);
+
+    // user accounts
+    let seller = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+    let buyer = ink_e2e::account_id(ink_e2e::AccountKeyring::Eve);
+
+    // transfer tokens to seller, buyer
+    let transfer_base = base_call_builder.transfer(seller, 1_000_000);
+    client
+        .call(&ink_e2e::alice(), &transfer_base)
+        .submit()
+        .await?;
+
+    let transfer_quote = quote_call_builder.transfer(buyer, 1_000_000);
+    client
+        .call(&ink_e2e::bob(), &transfer_quote)
+        .submit()
+        .await?;
+
+    // seller, buyer approve and deposit
+    let approve_base = base_call_builder.approve(dex.account_id, 1_000_000);
+    client
+        .call(&ink_e2e::dave(), &approve_base)
+        .submit()
+        .await?;
+
+    let approve_quote = quote_call_builder.approve(dex.account_id, 1_000_000);
+    client
+        .call(&ink_e2e::eve(), &approve_quote)
+        .submit()
+        .await?;
+
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000_000);
+    client
+        .call(&ink_e2e::dave(), &deposit_base)
+        .submit()
+        .await?;
+
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 1_000_000);
+    client
+        .call(&ink_e2e::eve(), &deposit_quote)
+        .submit()
+        .await?;
+
+    // seller rests 2 sell orders: 10 @ 90, 10 @ 100
+    let sell_order1 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        90,
+        10,
+        OrderOptions::default(),
+    );
+    client.call(&ink_e2e::dave(), &sell_order1).submit().await?;
+
+    let sell_order2 = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Sell,
+        100,
+        10,
+        OrderOptions::default(),
+    );
+    client.call(&ink_e2e::dave(), &sell_order2).submit().await?;
+
+    // buyer spends 1450 quote: 10 @ 90 + 5 @ 100, 50 quote left over
+    let market_order = dex_call_builder.place_market_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        MarketAmount::Quote(1450),
+        None,
+        None,
+    );
+    let market_result = client.call(&ink_e2e::eve(), &market_order).submit().await?;
+    let contract_events = market_result.contract_emitted_events().unwrap();
+    // minidex::NewOrder + 2 * (2 minidex::OrderFilled + minidex::Trade) + minidex::OrderCancelled
+    assert_eq!(contract_events.len(), 8);
+    // sized by quote: no price limit, fills report the quote left to spend
+    assert_event(&contract_events[0], |event: &NewOrder| {
+        assert_eq!(event.price, None);
+        assert_eq!(event.amount, MarketAmount::Quote(1450));
+    });
+    assert_event(&contract_events[2], |event: &OrderFilled| {
+        assert_eq!(event.filled_qty, 10);
+        assert_eq!(event.remaining, MarketAmount::Quote(550));
+    });
+    assert_event(&contract_events[5], |event: &OrderFilled| {
+        assert_eq!(event.filled_qty, 5);
+        assert_eq!(event.remaining, MarketAmount::Quote(50));
+    });
+    let fill = market_result.return_value().expect("place market_order");
+    assert_eq!(fill.filled_qty, 15);
+    assert_eq!(fill.quote_amount, 1400);
+    assert_eq!(fill.avg_price, 93);
+
+    // market order never rests: nothing locked, left over quote returned
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
+    let buyer_quote_locked_result = client
+        .call(&ink_e2e::eve(), &buyer_quote_locked)
+        .submit()
+        .await?;
+    assert_eq!(
+        buyer_quote_locked_result.return_value(),
+        0,
+        "buyer should have no quote tokens locked after market order"
+    );
+
+    let buyer_quote_balance = dex_call_builder.balance_of(quote.account_id);
+    let buyer_quote_result = client
+        .call(&ink_e2e::eve(), &buyer_quote_balance)
+        .submit()
+        .await?;
+    assert_eq!(
+        buyer_quote_result.return_value(),
+        998600,
+        "buyer should have 998600 quote tokens in vault (1000000 - 1400)"
+    );
+
+    Ok(())
+}
+
+#[ink_e2e::test]
+async fn test_pause_and_transfer_ownership<Client: ContractsBackend>(
+    mut client: Client,
+) -> E2EResult<()> {
+    // given
+    let (base, quote, _dex, _, _, mut dex_call_builder) = setup_contracts!(client);
+    let dave = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+
+    // only owner can pause
+    let pause = dex_call_builder.pause();
+    let pause_result = client.call(&ink_e2e::dave(), &pause).submit().await;
+    assert!(pause_result.is_err(), "non-owner pause should fail");
+
+    let pause_result = client.call(&ink_e2e::charlie(), &pause).submit().await?;
+    let contract_events = pause_result.contract_emitted_events().unwrap();
+    assert_eq!(contract_events.len(), 1); // minidex::Paused
+
+    // no new orders while paused
+    let buy_order = dex_call_builder.place_limit_order(
+        (base.account_id, quote.account_id),
+        Side::Buy,
+        100,
+        10,
+        OrderOptions::default(),
+    );
+    let buy_result = client.call(&ink_e2e::dave(), &buy_order).submit().await;
+    assert!(buy_result.is_err(), "place order while paused should fail");
+
+    let unpause = dex_call_builder.unpause();
+    client.call(&ink_e2e::charlie(), &unpause).submit().await?;
+    let is_paused = dex_call_builder.is_paused();
+    let is_paused_result = client.call(&ink_e2e::dave(), &is_paused).submit().await?;
+    assert!(!is_paused_result.return_value());
+
+    // two step ownership transfer
+    let transfer = dex_call_builder.transfer_ownership(dave);
+    let transfer_result = client.call(&ink_e2e::dave(), &transfer).submit().await;
+    assert!(transfer_result.is_err(), "non-owner transfer should fail");
+    client.call(&ink_e2e::charlie(), &transfer).submit().await?;
+
+    let accept = dex_call_builder.accept_ownership();
+    let accept_result = client.call(&ink_e2e::eve(), &accept).submit().await;
+    assert!(accept_result.is_err(), "only pending owner can accept");
+    let accept_result = client.call(&ink_e2e::dave(), &accept).submit().await?;
+    let contract_events = accept_result.contract_emitted_events().unwrap();
+    assert_eq!(contract_events.len(), 1); // minidex::OwnershipTransferred
+
+    let owner = dex_call_builder.owner();
+    let owner_result = client.call(&ink_e2e::eve(), &owner).submit().await?;
+    assert_eq!(owner_result.return_value(), dave);
+
+    // previous owner lost its powers
+    let pause_result = client.call(&ink_e2e::charlie(), &pause).submit().await;
+    assert!(pause_result.is_err(), "previous owner pause should fail");
+
+    Ok(())
+}
+
+#[ink_e2e::test]
+async fn test_upgrade_and_migrate<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
+    // given
+    let (base, quote, dex, mut base_call_builder, _, mut dex_call_builder) =
+        setup_contracts!(client);
+    let pair = (base.account_id, quote.account_id);
+
+    let version = dex_call_builder.version();
+    let version_result = client.call(&ink_e2e::dave(), &version).submit().await?;
+    assert_eq!(version_result.return_value(), STORAGE_VERSION);
+
+    // only owner can upgrade
+    let upgrade = dex_call_builder.upgrade(Default::default());
+    let upgrade_result = client.call(&ink_e2e::dave(), &upgrade).submit().await;
+    assert!(upgrade_result.is_err(), "non-owner upgrade should fail");
+
+    // unknown code hash
+    let upgrade_result = client.call(&ink_e2e::charlie(), &upgrade).submit().await;
+    assert!(
+        upgrade_result.is_err(),
+        "upgrade to unknown code should fail"
+    );
+
+    // nothing to migrate on fresh storage
+    let migrate = dex_call_builder.migrate();
+    let migrate_result = client.call(&ink_e2e::charlie(), &migrate).submit().await;
+    assert!(
+        migrate_result.is_err(),
+        "migrate up to date storage should fail"
+    );
+
+    // seller deposits base and rests a sell before the upgrade
+    let seller = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+    let transfer_base = base_call_builder.transfer(seller, 1_000);
+    client
+        .call(&ink_e2e::alice(), &transfer_base)
+        .submit()
+        .await?;
+    let approve_base = base_call_builder.approve(dex.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &approve_base)
+        .submit()
+        .await?;
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &deposit_base)
+        .submit()
+        .await?;
+    let place_order =
+        dex_call_builder.place_limit_order(pair, Side::Sell, 100, 10, OrderOptions::default());
+    let place_order_result = client.call(&ink_e2e::dave(), &place_order).submit().await?;
+    let order_id = place_order_result.return_value().expect("place order");
+
+    // when
+    let code_hash = client
+        .upload("minidex", &ink_e2e::charlie())
+        .submit()
+        .await
+        .expect("upload failed")
+        .code_hash;
+    let upgrade = dex_call_builder.upgrade(code_hash);
+    let upgrade_result = client.call(&ink_e2e::charlie(), &upgrade).submit().await;
+    assert!(upgrade_result.is_ok(), "upgrade should succeed");
+
+    // then
+    // the storage is already at the version of the new code
+    let migrate_result = client.call(&ink_e2e::charlie(), &migrate).submit().await;
+    assert!(
+        migrate_result.is_err(),
+        "migrate up to date storage should fail"
+    );
+    let version_result = client.call(&ink_e2e::dave(), &version).submit().await?;
+    assert_eq!(version_result.return_value(), STORAGE_VERSION);
+
+    // owner, balances and orders stay in place
+    let owner = dex_call_builder.owner();
+    let owner_result = client.call(&ink_e2e::dave(), &owner).submit().await?;
+    assert_eq!(
+        owner_result.return_value(),
+        ink_e2e::account_id(ink_e2e::AccountKeyring::Charlie)
+    );
+    let balance_of = dex_call_builder.balance_of(base.account_id);
+    let balance_of_result = client.call(&ink_e2e::dave(), &balance_of).submit().await?;
+    assert_eq!(balance_of_result.return_value(), 990);
+    let locked_of = dex_call_builder.locked_of(base.account_id);
+    let locked_of_result = client.call(&ink_e2e::dave(), &locked_of).submit().await?;
+    assert_eq!(locked_of_result.return_value(), 10);
+    let get_order = dex_call_builder.get_order(order_id);
+    let get_order_result = client.call(&ink_e2e::dave(), &get_order).submit().await?;
+    let order = get_order_result
+        .return_value()
+        .expect("get order")
+        .expect("resting order");
+    assert_eq!((order.price, order.qty, order.locked), (100, 10, 10));
+
+    // and the book keeps working
+    let cancel_order = dex_call_builder.cancel_order(order_id);
+    let cancel_order_result = client.call(&ink_e2e::dave(), &cancel_order).submit().await;
+    assert!(cancel_order_result.is_ok(), "cancel order should succeed");
+    let locked_of_result = client.call(&ink_e2e::dave(), &locked_of).submit().await?;
+    assert_eq!(locked_of_result.return_value(), 0);
+
+    Ok(())
+}
+
+#[ink_e2e::test]
+async fn test_batch_orders<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
+    // given
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+        setup_contracts!(client);
+    let pair = (base.account_id, quote.account_id);
+
+    // seller gets and deposits base
+    let seller = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+    let transfer_base = base_call_builder.transfer(seller, 1_000);
+    client
+        .call(&ink_e2e::alice(), &transfer_base)
+        .submit()
+        .await?;
+    let approve_base = base_call_builder.approve(dex.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &approve_base)
+        .submit()
+        .await?;
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &deposit_base)
+        .submit()
+        .await?;
+
+    let place = |price| BatchOp::Place {
+        pair,
+        side: Side::Sell,
+        price,
+        qty: 10,
+        options: OrderOptions::default(),
+    };
+
+    // best effort: the unknown order fails on its own
+    let batch = dex_call_builder.batch(
+        vec![place(100), place(101), BatchOp::Cancel { order_id: 999 }],
+        false,
+    );
+    let batch_result = client.call(&ink_e2e::dave(), &batch).submit().await?;
+    let results = batch_result.return_value().expect("batch");
+    assert_eq!(results.len(), 3);
+    let order_ids: Vec<u64> = results[..2].iter().map(|r| *r.as_ref().unwrap()).collect();
+    assert!(results[2].is_err());
+
+    // atomic: a failing operation fails the whole batch
+    let batch = dex_call_builder.batch(vec![place(102), BatchOp::Cancel { order_id: 999 }], true);
+    let batch_result = client.call(&ink_e2e::dave(), &batch).submit().await;
+    assert!(batch_result.is_err(), "atomic batch should fail");
+
+    // buyer gets and deposits quote
+    let buyer = ink_e2e::account_id(ink_e2e::AccountKeyring::Ferdie);
+    let transfer_quote = quote_call_builder.transfer(buyer, 10_000);
+    client
+        .call(&ink_e2e::bob(), &transfer_quote)
+        .submit()
+        .await?;
+    let approve_quote = quote_call_builder.approve(dex.account_id, 10_000);
+    client
+        .call(&ink_e2e::ferdie(), &approve_quote)
+        .submit()
+        .await?;
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 10_000);
+    client
+        .call(&ink_e2e::ferdie(), &deposit_quote)
+        .submit()
+        .await?;
+
+    // best effort still fails the whole batch once an operation fails while matching:
+    // the fill-or-kill buy passes its checks, lifts the ask at 100 and is then capped
+    let bid = BatchOp::Place {
+        pair,
+        side: Side::Buy,
+        price: 99,
+        qty: 10,
+        options: OrderOptions::default(),
+    };
+    let fill_or_kill = BatchOp::Place {
+        pair,
+        side: Side::Buy,
+        price: 101,
+        qty: 20,
+        options: OrderOptions {
+            time_in_force: TimeInForce::FillOrKill,
+            max_fills: Some(1),
+            ..Default::default()
+        },
+    };
+    let batch = dex_call_builder.batch(vec![bid, fill_or_kill], false);
+    let batch_result = client.call(&ink_e2e::ferdie(), &batch).submit().await;
+    assert!(
+        batch_result.is_err(),
+        "batch failing while matching should fail"
+    );
+
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
+    let buyer_quote_locked_result = client
+        .call(&ink_e2e::ferdie(), &buyer_quote_locked)
+        .submit()
+        .await?;
+    assert_eq!(buyer_quote_locked_result.return_value(), 0);
+
+    // cancel both orders at once
+    let batch = dex_call_builder.batch(
+        order_ids
+            .iter()
+            .map(|&order_id| BatchOp::Cancel { order_id })
+            .collect(),
+        true,
+    );
+    let batch_result = client.call(&ink_e2e::dave(), &batch).submit().await?;
+    let contract_events = batch_result.contract_emitted_events().unwrap();
+    assert_eq!(contract_events.len(), 2); // 2 minidex::OrderCancelled
+
+    let seller_base_locked = dex_call_builder.locked_of(base.account_id);
+    let seller_base_locked_result = client
+        .call(&ink_e2e::dave(), &seller_base_locked)
+        .submit()
+        .await?;
+    assert_eq!(seller_base_locked_result.return_value(), 0);
+
+    Ok(())
+}
+
+#[ink_e2e::test]
+async fn test_client_order_id<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
+    // given
+    let (base, quote, dex, mut base_call_builder, _, mut dex_call_builder) =
+        setup_contracts!(client);
+    let pair = (base.account_id, quote.account_id);
+
+    // seller gets and deposits base
+    let seller = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+    let transfer_base = base_call_builder.transfer(seller, 1_000);
+    client
+        .call(&ink_e2e::alice(), &transfer_base)
+        .submit()
+        .await?;
+    let approve_base = base_call_builder.approve(dex.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &approve_base)
+        .submit()
+        .await?;
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &deposit_base)
+        .submit()
+        .await?;
+
+    let options = OrderOptions {
+        client_order_id: Some(42),
+        ..Default::default()
+    };
+    let place_order = dex_call_builder.place_limit_order(pair, Side::Sell, 100, 10, options);
+    let place_order_result = client.call(&ink_e2e::dave(), &place_order).submit().await?;
+    let order_id = place_order_result.return_value().expect("place order");
+
+    // the submitter can look the order up by its own id
+    let order_id_of_client = dex_call_builder.order_id_of_client(seller, 42);
+    let order_id_of_client_result = client
+        .call(&ink_e2e::dave(), &order_id_of_client)
+        .submit()
+        .await?;
+    assert_eq!(order_id_of_client_result.return_value(), Ok(Some(order_id)));
+
+    // resubmitting is rejected
+    let place_order_result = client.call(&ink_e2e::dave(), &place_order).submit().await;
+    assert!(
+        place_order_result.is_err(),
+        "duplicate client order id should fail"
+    );
+
+    // cancel by client order id
+    let cancel = dex_call_builder.cancel_by_client_id(42);
+    let cancel_result = client.call(&ink_e2e::dave(), &cancel).submit().await?;
+    assert_eq!(cancel_result.return_value(), Ok(order_id));
+
+    let seller_base_locked = dex_call_builder.locked_of(base.account_id);
+    let seller_base_locked_result = client
+        .call(&ink_e2e::dave(), &seller_base_locked)
+        .submit()
+        .await?;
+    assert_eq!(seller_base_locked_result.return_value(), 0);
+
+    // the id stays used after the order is gone
+    let place_order_result = client.call(&ink_e2e::dave(), &place_order).submit().await;
+    assert!(
+        place_order_result.is_err(),
+        "used client order id should fail"
+    );
+
+    Ok(())
+}
+
+#[ink_e2e::test]
+async fn test_stop_orders<Client: ContractsBackend>(mut client: Client) -> E2EResult<()> {
+    // given
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+        setup_contracts!(client);
+    let pair = (base.account_id, quote.account_id);
+
+    // seller gets and deposits base, buyer quote
+    let seller = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+    let buyer = ink_e2e::account_id(ink_e2e::AccountKeyring::Ferdie);
+    let transfer_base = base_call_builder.transfer(seller, 1_000);
+    client
+        .call(&ink_e2e::alice(), &transfer_base)
+        .submit()
+        .await?;
+    let transfer_quote = quote_call_builder.transfer(buyer, 10_000);
+    client
+        .call(&ink_e2e::bob(), &transfer_quote)
+        .submit()
+        .await?;
+    let approve_base = base_call_builder.approve(dex.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &approve_base)
+        .submit()
+        .await?;
+    let approve_quote = quote_call_builder.approve(dex.account_id, 10_000);
+    client
+        .call(&ink_e2e::ferdie(), &approve_quote)
+        .submit()
+        .await?;
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &deposit_base)
+        .submit()
+        .await?;
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 10_000);
+    client
+        .call(&ink_e2e::ferdie(), &deposit_quote)
+        .submit()
+        .await?;
+
+    // seller asks 10 @ 100 and 10 @ 101
+    for price in [100, 101] {
+        let sell_order = dex_call_builder.place_limit_order(
+            pair,
+            Side::Sell,
+            price,
+            10,
+            OrderOptions::default(),
+        );
+        client.call(&ink_e2e::dave(), &sell_order).submit().await?;
+    }
+
+    // buyer stops in above 100, limited at 101
+    let stop_order = dex_call_builder.place_stop_order(
+        pair,
+        Side::Buy,
+        StopKind::Limit,
+        Trigger::AtOrAbove(100),
+        101,
+        10,
+    );
+    let stop_result = client
+        .call(&ink_e2e::ferdie(), &stop_order)
+        .submit()
+        .await?;
+    let stop_id = stop_result.return_value().expect("place stop order");
+
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
+    let buyer_quote_locked_result = client
+        .call(&ink_e2e::ferdie(), &buyer_quote_locked)
+        .submit()
+        .await?;
+    assert_eq!(buyer_quote_locked_result.return_value(), 1_010);
+
+    // a trade at 100 fires the stop, which lifts the ask at 101
+    let buy_order =
+        dex_call_builder.place_limit_order(pair, Side::Buy, 100, 10, OrderOptions::default());
+    let buy_result = client.call(&ink_e2e::ferdie(), &buy_order).submit().await?;
+    let contract_events = buy_result.contract_emitted_events().unwrap();
+    // minidex::NewOrder + 2 minidex::OrderFilled + minidex::Trade, then
+    // minidex::StopTriggered + minidex::NewOrder + 2 minidex::OrderFilled + minidex::Trade
+    assert_eq!(contract_events.len(), 9);
+    assert_event(&contract_events[4], |event: &StopTriggered| {
+        assert_eq!(event.stop_id, stop_id);
+        assert_eq!(event.last_price, 100);
+    });
+    assert_event(&contract_events[7], |event: &OrderFilled| {
+        assert_eq!(event.filled_price, 101);
+        assert_eq!(event.filled_qty, 10);
+    });
+
+    let last_price = dex_call_builder.last_price(pair);
+    let last_price_result = client
+        .call(&ink_e2e::ferdie(), &last_price)
+        .submit()
+        .await?;
+    assert_eq!(last_price_result.return_value(), Ok(Some(101)));
+
+    let get_stop_order = dex_call_builder.get_stop_order(stop_id);
+    let get_stop_order_result = client
+        .call(&ink_e2e::ferdie(), &get_stop_order)
+        .submit()
+        .await?;
+    assert_eq!(get_stop_order_result.return_value(), Ok(None));
+
+    // seller stops out below 90, then cancels
+    let stop_order = dex_call_builder.place_stop_order(
+        pair,
+        Side::Sell,
+        StopKind::Market,
+        Trigger::AtOrBelow(90),
+        80,
+        10,
+    );
+    let stop_result = client.call(&ink_e2e::dave(), &stop_order).submit().await?;
+    let stop_id = stop_result.return_value().expect("place stop order");
+
+    let cancel_stop_order = dex_call_builder.cancel_stop_order(stop_id);
+    let cancel_result = client
+        .call(&ink_e2e::dave(), &cancel_stop_order)
+        .submit()
+        .await?;
+    let contract_events = cancel_result.contract_emitted_events().unwrap();
+    assert_eq!(contract_events.len(), 1); // minidex::StopCancelled
+
+    let seller_base_locked = dex_call_builder.locked_of(base.account_id);
+    let seller_base_locked_result = client
+        .call(&ink_e2e::dave(), &seller_base_locked)
+        .submit()
+        .await?;
+    assert_eq!(seller_base_locked_result.return_value(), 0);
+
+    Ok(())
+}
+
+#[ink_e2e::test]
+async fn test_stop_fills_carry_over_to_crank<Client: ContractsBackend>(
+    mut client: Client,
+) -> E2EResult<()> {
+    // given
+    let (base, quote, dex, mut base_call_builder, mut quote_call_builder, mut dex_call_builder) =
+        setup_contracts!(client);
+    let pair = (base.account_id, quote.account_id);
+
+    // seller gets and deposits base, buyer quote
+    let seller = ink_e2e::account_id(ink_e2e::AccountKeyring::Dave);
+    let buyer = ink_e2e::account_id(ink_e2e::AccountKeyring::Ferdie);
+    let transfer_base = base_call_builder.transfer(seller, 1_000);
+    client
+        .call(&ink_e2e::alice(), &transfer_base)
+        .submit()
+        .await?;
+    let transfer_quote = quote_call_builder.transfer(buyer, 10_000);
+    client
+        .call(&ink_e2e::bob(), &transfer_quote)
+        .submit()
+        .await?;
+    let approve_base = base_call_builder.approve(dex.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &approve_base)
+        .submit()
+        .await?;
+    let approve_quote = quote_call_builder.approve(dex.account_id, 10_000);
+    client
+        .call(&ink_e2e::ferdie(), &approve_quote)
+        .submit()
+        .await?;
+    let deposit_base = dex_call_builder.deposit(base.account_id, 1_000);
+    client
+        .call(&ink_e2e::dave(), &deposit_base)
+        .submit()
+        .await?;
+    let deposit_quote = dex_call_builder.deposit(quote.account_id, 10_000);
+    client
+        .call(&ink_e2e::ferdie(), &deposit_quote)
+        .submit()
+        .await?;
+
+    // seller asks 1 @ 100, then 1 @ 101 once more than a fired stop may match
+    for price in core::iter::once(100).chain([101; MAX_STOP_FILLS as usize + 1]) {
+        let sell_order =
+            dex_call_builder.place_limit_order(pair, Side::Sell, price, 1, OrderOptions::default());
+        client.call(&ink_e2e::dave(), &sell_order).submit().await?;
+    }
+
+    // buyer stops in above 100 at market, no worse than 101, for one more than the asks
+    let stop_order = dex_call_builder.place_stop_order(
+        pair,
+        Side::Buy,
+        StopKind::Market,
+        Trigger::AtOrAbove(100),
+        101,
+        u128::from(MAX_STOP_FILLS) + 2,
+    );
+    client
+        .call(&ink_e2e::ferdie(), &stop_order)
+        .submit()
+        .await?;
+
+    // a trade at 100 fires the stop, which matches up to its max fills and waits
+    let buy_order =
+        dex_call_builder.place_limit_order(pair, Side::Buy, 100, 1, OrderOptions::default());
+    let buy_result = client.call(&ink_e2e::ferdie(), &buy_order).submit().await?;
+    let contract_events = buy_result.contract_emitted_events().unwrap();
+    let trades = contract_events
+        .iter()
+        .filter_map(decode_event::<Trade, _>)
+        .count();
+    assert_eq!(trades, 1 + MAX_STOP_FILLS as usize);
+    assert!(decode_event::<OrderPending, _>(contract_events.last().unwrap()).is_some());
+
+    // the crank lifts the last ask and unlocks what is left of the stop-market order
+    let crank = dex_call_builder.crank(pair, 10);
+    let crank_result = client.call(&ink_e2e::eve(), &crank).submit().await?;
+    let contract_events = crank_result.contract_emitted_events().unwrap();
+    // 2 minidex::OrderFilled + minidex::Trade + minidex::OrderCancelled
+    assert_eq!(contract_events.len(), 4);
+    assert!(decode_event::<OrderCancelled, _>(&contract_events[3]).is_some());
+    assert_eq!(crank_result.return_value(), Ok(1));
+
+    let buyer_quote_locked = dex_call_builder.locked_of(quote.account_id);
+    let buyer_quote_locked_result = client
+        .call(&ink_e2e::ferdie(), &buyer_quote_locked)
+        .submit()
+        .await?;
+    assert_eq!(buyer_quote_locked_result.return_value(), 0);
+
+    Ok(())
+}
@@ -1,21 +1,122 @@
-use ink::prelude::string::String;
-
 use crate::types::Token;
 
+/// Errors returned by the DEX.
+///
+/// Each variant keeps its SCALE index across releases, so clients can match on the
+/// encoded discriminant. New variants are only ever appended.
 #[allow(clippy::cast_possible_truncation)]
 #[ink::scale_derive(Encode, Decode, TypeInfo)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
+    /// The DEX is allowed to move less of the token than requested.
+    #[codec(index = 0)]
     InsufficientAllowance(Token),
-    InsufficientToken(Token),
-    InvalidQuantity(String),
-    InvalidPrice(String),
-    InvalidOrder(String),
+    /// The ERC20 contract of the token rejected a transfer.
+    #[codec(index = 1)]
+    Erc20(Token, erc20::Error),
+    /// No market lists the token.
+    #[codec(index = 2)]
+    TokenNotSupported(Token),
+    /// No open order has the ID.
+    #[codec(index = 3)]
     OrderNotFound(u64),
+    /// The available balance of the token is too low.
+    #[codec(index = 4)]
     InsufficientBalance(Token),
+    /// The locked balance of the token is too low.
+    #[codec(index = 5)]
     InsufficientLockedBalance(Token),
-    Unauthorized(String),
-    InvalidTransfer(String),
+    /// The caller is not the contract owner.
+    #[codec(index = 6)]
+    NotOwner,
+    /// The caller is not the proposed owner.
+    #[codec(index = 7)]
+    NotPendingOwner,
+    /// The caller does not own the order.
+    #[codec(index = 8)]
+    NotOrderOwner,
+    /// Order placement is halted by the owner.
+    #[codec(index = 9)]
+    TradingPaused,
+    /// The code hash was never uploaded.
+    #[codec(index = 10)]
+    UpgradeFailed,
+    /// Storage is already at the current layout version.
+    #[codec(index = 11)]
+    AlreadyMigrated,
+    /// A deposit or withdrawal amount is zero.
+    #[codec(index = 12)]
+    ZeroAmount,
+    /// An order price is zero.
+    #[codec(index = 13)]
+    ZeroPrice,
+    /// An order quantity is zero.
+    #[codec(index = 14)]
+    ZeroQuantity,
+    /// An iceberg display quantity is zero.
+    #[codec(index = 15)]
+    ZeroDisplayQuantity,
+    /// A max fills cap is zero.
+    #[codec(index = 16)]
+    ZeroMaxFills,
+    /// No market is listed for the (base, quote) pair.
+    #[codec(index = 17)]
+    PairNotSupported,
+    /// A market must trade two different tokens.
+    #[codec(index = 18)]
+    IdenticalTokens,
+    /// The market is already listed.
+    #[codec(index = 19)]
+    MarketExists,
+    /// A fee rate exceeds 10000 basis points.
+    #[codec(index = 20)]
+    FeeRateTooHigh,
+    /// A fee exceeds the amount it is taken from.
+    #[codec(index = 21)]
+    FeeExceedsAmount,
+    /// Locked tokens cannot be transferred to their own account.
+    #[codec(index = 22)]
+    SelfTransfer,
+    /// An order expiry is not after the current block timestamp, or a modified order has
+    /// expired.
+    #[codec(index = 23)]
+    ExpiryInPast,
+    /// The account already used the client order ID.
+    #[codec(index = 24)]
+    ClientOrderIdUsed,
+    /// The account never used the client order ID.
+    #[codec(index = 25)]
+    ClientOrderIdNotFound(u64),
+    /// A post-only order would match a resting order.
+    #[codec(index = 26)]
+    PostOnlyWouldCross,
+    /// A fill-or-kill order hit its max fills before it was completely filled.
+    #[codec(index = 27)]
+    FillOrKillMaxFills,
+    /// The book cannot fill a fill-or-kill order completely.
+    #[codec(index = 28)]
+    FillOrKillNotFilled,
+    /// A market sell must be sized by base quantity.
+    #[codec(index = 29)]
+    MarketSellByQuote,
+    /// A market order sized by base quantity needs a worst price.
+    #[codec(index = 30)]
+    WorstPriceRequired,
+    /// An order waiting for the crank cannot be modified.
+    #[codec(index = 31)]
+    PendingOrderNotModifiable,
+    /// The last trade price already meets the stop trigger.
+    #[codec(index = 32)]
+    StopWouldTrigger,
+    /// The book has no level at the price, storage is inconsistent.
+    #[codec(index = 33)]
+    PriceLevelNotFound,
+    /// Storage holds an older layout version until `migrate` is done.
+    #[codec(index = 34)]
+    MigrationPending,
+    /// An amount, balance or counter does not fit in its integer type.
+    #[codec(index = 35)]
+    Overflow,
 }
 
 pub type Result<T> = core::result::Result<T, Error>;
@@ -11,19 +11,61 @@ pub mod minidex {
     use super::*;
     use erc20::Erc20Ref;
     use error::{Error, Result};
-    use ink::env::call::FromAccountId;
-    use storage::{BTreeOrderBook, Vault};
-    use traits::{order_book::OrderBook, token_vault::TokenVault};
-    use types::{EventFilled, Side, Token};
+    use ink::prelude::vec::Vec;
+    use ink::{
+        env::call::FromAccountId,
+        storage::{traits::ManualKey, Lazy, Mapping},
+    };
+    use storage::{BTreeOrderBook, BTreeTriggerBook, Vault};
+    use traits::{order_book::OrderBook, token_vault::TokenVault, trigger_book::TriggerBook};
+    use types::{
+        BatchOp, BookEvent, Depth, EventExpired, EventFilled, EventTrade, MarketAmount, MarketFill,
+        Order, OrderOptions, Pair, Side, Spread, StopKind, StopOrder, TimeInForce, Token, Trigger,
+    };
 
-    #[allow(clippy::new_without_default)]
+    /// Version of the storage layout written by this code.
+    pub const STORAGE_VERSION: u32 = 1;
+
+    /// Storage key of the layout version, outside the root struct so any code can read it.
+    const VERSION_KEY: u32 = 0x7665_7273;
+
+    /// Maximum number of stop orders fired by a single call, the rest fire on the next
+    /// trade or `crank`.
+    pub const MAX_STOP_TRIGGERS: u32 = 10;
+
+    /// Maximum number of maker orders a fired stop order matches within the call that
+    /// fires it, the crank matches the rest.
+    pub const MAX_STOP_FILLS: u32 = 5;
+
+    /// The root fields, and the packed fields of the books and vault, are decoded on every
+    /// call and must keep their layout across versions. New data goes behind a `Mapping`
+    /// or `Lazy`, and changed entries are converted by `migrate`.
     #[ink(storage)]
     pub struct MiniDex {
         owner: AccountId,
+        // layout version of the data in storage
+        version: Lazy<u32, ManualKey<VERSION_KEY>>,
+        // proposed owner until it accepts
+        pending_owner: Option<AccountId>,
+        // halts order placement, cancels and withdrawals still work
+        paused: bool,
         order_book: BTreeOrderBook,
+        // stop orders waiting for their trigger price
+        trigger_book: BTreeTriggerBook,
         vault: Vault,
-        base_token_contract: Erc20Ref,
-        quote_token_contract: Erc20Ref,
+        // token contracts listed by any market
+        tokens: Mapping<Token, ()>,
+    }
+
+    /// Event emitted when a new market is listed.
+    #[ink(event)]
+    pub struct MarketAdded {
+        /// The base token contract of the market.
+        #[ink(topic)]
+        pub(crate) base: Token,
+        /// The quote token contract of the market.
+        #[ink(topic)]
+        pub(crate) quote: Token,
     }
 
     /// Event emitted when a new order is created.
@@ -32,12 +74,78 @@ pub mod minidex {
         /// The unique identifier of the order.
         #[ink(topic)]
         pub(crate) order_id: u64,
-        /// The price of the order.
+        /// The account placing the order.
+        #[ink(topic)]
+        pub(crate) owner: AccountId,
+        /// The trading pair (base, quote) of the order.
+        pub(crate) pair: Pair,
+        /// The side of the order.
+        pub(crate) side: Side,
+        /// The limit or worst price of the order, `None` for a market buy without one.
+        pub(crate) price: Option<u128>,
+        /// The base quantity of the order, or the quote amount a market buy spends.
+        pub(crate) amount: MarketAmount,
+        /// The id chosen by the owner, if any.
+        pub(crate) client_order_id: Option<u64>,
+    }
+
+    /// Event emitted when a stop order is placed.
+    #[ink(event)]
+    pub struct NewStopOrder {
+        /// The unique identifier of the stop order.
+        #[ink(topic)]
+        pub(crate) stop_id: u64,
+        /// The last trade price condition that fires the order.
+        pub(crate) trigger: Trigger,
+        /// The limit or worst price of the order.
         pub(crate) price: u128,
         /// The quantity of the order.
         pub(crate) qty: u128,
     }
 
+    /// Event emitted when the last trade price reaches a stop order, placing a new order.
+    #[ink(event)]
+    pub struct StopTriggered {
+        /// The unique identifier of the stop order.
+        #[ink(topic)]
+        pub(crate) stop_id: u64,
+        /// The unique identifier of the order it turned into.
+        #[ink(topic)]
+        pub(crate) order_id: u64,
+        /// The trade price that fired the stop.
+        pub(crate) last_price: u128,
+    }
+
+    /// Event emitted when a stop order is cancelled before it triggered.
+    #[ink(event)]
+    pub struct StopCancelled {
+        /// The unique identifier of the cancelled stop order.
+        #[ink(topic)]
+        pub(crate) stop_id: u64,
+    }
+
+    /// Event emitted when an order stopped by its max fills still crosses the book.
+    ///
+    /// The order waits off the book until `crank` matches it further.
+    #[ink(event)]
+    pub struct OrderPending {
+        /// The unique identifier of the pending order.
+        #[ink(topic)]
+        pub(crate) order_id: u64,
+    }
+
+    /// Event emitted when the price or quantity of an order is changed.
+    #[ink(event)]
+    pub struct OrderModified {
+        /// The unique identifier of the modified order.
+        #[ink(topic)]
+        pub(crate) order_id: u64,
+        /// The new price of the order.
+        pub(crate) price: u128,
+        /// The unfilled quantity left after matching at the new price, zero once filled.
+        pub(crate) qty: u128,
+    }
+
     /// Event emitted when an order is cancelled.
     #[ink(event)]
     pub struct OrderCancelled {
@@ -56,6 +164,27 @@ pub mod minidex {
         pub(crate) filled_price: u128,
         /// The quantity that was filled.
         pub(crate) filled_qty: u128,
+        /// What is left of the order after this fill, in quote for a market buy sized by
+        /// quote amount.
+        pub(crate) remaining: MarketAmount,
+        /// The fee charged, in base for buys and in quote for sells.
+        pub(crate) fee: u128,
+    }
+
+    /// Event emitted when a resting order expired and was removed.
+    #[ink(event)]
+    pub struct OrderExpired {
+        /// The unique identifier of the expired order.
+        #[ink(topic)]
+        pub(crate) order_id: u64,
+    }
+
+    impl From<EventExpired> for OrderExpired {
+        fn from(e: EventExpired) -> Self {
+            Self {
+                order_id: e.order_id,
+            }
+        }
     }
 
     impl From<EventFilled> for OrderFilled {
@@ -64,17 +193,137 @@ pub mod minidex {
                 order_id: e.order_id,
                 filled_price: e.filled_price,
                 filled_qty: e.filled_qty,
+                remaining: MarketAmount::Base(e.remaining_qty),
+                fee: e.fee,
+            }
+        }
+    }
+
+    /// Event emitted once per match between an incoming order and a resting order.
+    #[ink(event)]
+    pub struct Trade {
+        /// The unique identifier of the trade.
+        pub(crate) trade_id: u64,
+        /// The trading pair (base, quote) of the trade.
+        pub(crate) pair: Pair,
+        /// The resting order that was matched.
+        pub(crate) maker_order_id: u64,
+        /// The incoming order that matched it.
+        pub(crate) taker_order_id: u64,
+        /// The owner of the resting order.
+        #[ink(topic)]
+        pub(crate) maker: AccountId,
+        /// The owner of the incoming order.
+        #[ink(topic)]
+        pub(crate) taker: AccountId,
+        /// The side of the incoming order.
+        pub(crate) taker_side: Side,
+        /// The execution price.
+        pub(crate) price: u128,
+        /// The base quantity traded.
+        pub(crate) qty: u128,
+        /// The quote amount paid for it.
+        pub(crate) quote_amount: u128,
+    }
+
+    impl From<EventTrade> for Trade {
+        fn from(e: EventTrade) -> Self {
+            Self {
+                trade_id: e.trade_id,
+                pair: e.pair,
+                maker_order_id: e.maker_order_id,
+                taker_order_id: e.taker_order_id,
+                maker: e.maker,
+                taker: e.taker,
+                taker_side: e.taker_side,
+                price: e.price,
+                qty: e.qty,
+                quote_amount: e.quote_amount,
             }
         }
     }
 
+    /// Event emitted when the owner changes the fee rates.
+    #[ink(event)]
+    pub struct FeeRatesUpdated {
+        /// The fee rate of resting orders, in basis points.
+        pub(crate) maker_fee_bps: u16,
+        /// The fee rate of incoming orders, in basis points.
+        pub(crate) taker_fee_bps: u16,
+    }
+
+    /// Event emitted when the owner withdraws accrued fees.
+    #[ink(event)]
+    pub struct FeesWithdrawn {
+        /// The token contract of the fees.
+        #[ink(topic)]
+        pub(crate) token: Token,
+        /// The amount of fees withdrawn.
+        pub(crate) amount: u128,
+    }
+
+    /// Event emitted when the owner pauses trading.
+    #[ink(event)]
+    pub struct Paused {
+        /// The owner who paused trading.
+        #[ink(topic)]
+        pub(crate) account: AccountId,
+    }
+
+    /// Event emitted when the owner resumes trading.
+    #[ink(event)]
+    pub struct Unpaused {
+        /// The owner who resumed trading.
+        #[ink(topic)]
+        pub(crate) account: AccountId,
+    }
+
+    /// Event emitted when the owner proposes a new owner.
+    #[ink(event)]
+    pub struct OwnershipTransferStarted {
+        /// The current owner.
+        #[ink(topic)]
+        pub(crate) previous_owner: AccountId,
+        /// The proposed owner, who still has to accept.
+        #[ink(topic)]
+        pub(crate) new_owner: AccountId,
+    }
+
+    /// Event emitted when the proposed owner accepts ownership.
+    #[ink(event)]
+    pub struct OwnershipTransferred {
+        /// The previous owner.
+        #[ink(topic)]
+        pub(crate) previous_owner: AccountId,
+        /// The new owner.
+        #[ink(topic)]
+        pub(crate) new_owner: AccountId,
+    }
+
+    /// Event emitted when the owner replaces the contract code.
+    #[ink(event)]
+    pub struct Upgraded {
+        /// The hash of the new contract code.
+        #[ink(topic)]
+        pub(crate) code_hash: Hash,
+    }
+
+    /// Event emitted when storage is migrated to the layout of the current code.
+    #[ink(event)]
+    pub struct Migrated {
+        /// The storage version before the migration.
+        pub(crate) from_version: u32,
+        /// The storage version after the migration.
+        pub(crate) to_version: u32,
+    }
+
     /// Event emitted when tokens are deposited into the DEX.
     #[ink(event)]
     pub struct Deposit {
         /// The account that deposited the tokens.
         #[ink(topic)]
         pub(crate) account: AccountId,
-        /// The type of token that was deposited.
+        /// The token contract that was deposited.
         #[ink(topic)]
         pub(crate) token: Token,
         /// The amount of tokens deposited.
@@ -87,7 +336,7 @@ pub mod minidex {
         /// The account that withdrew the tokens.
         #[ink(topic)]
         pub(crate) account: AccountId,
-        /// The type of token that was withdrawn.
+        /// The token contract that was withdrawn.
         #[ink(topic)]
         pub(crate) token: Token,
         /// The amount of tokens withdrawn.
@@ -95,39 +344,333 @@ pub mod minidex {
     }
 
     impl MiniDex {
-        /// Creates a new DEX instance.
-        ///
-        /// # Arguments
-        /// * `base_contract_addr` - The address of the base token contract
-        /// * `quote_contract_addr` - The address of the quote token contract
+        /// Creates a new DEX instance without any market.
         ///
         /// # Returns
         /// * A new instance of the DEX contract
+        #[allow(clippy::new_without_default)]
         #[ink(constructor)]
-        pub fn new(base_contract_addr: AccountId, quote_contract_addr: AccountId) -> Self {
+        pub fn new() -> Self {
             let owner = Self::env().caller();
-            let base = Erc20Ref::from_account_id(base_contract_addr);
-            let quote = Erc20Ref::from_account_id(quote_contract_addr);
+            let mut version = Lazy::new();
+            version.set(&STORAGE_VERSION);
             Self {
                 owner,
+                version,
+                pending_owner: None,
+                paused: false,
                 order_book: BTreeOrderBook::new(),
+                trigger_book: BTreeTriggerBook::new(),
                 vault: Default::default(),
-                base_token_contract: base,
-                quote_token_contract: quote,
+                tokens: Mapping::default(),
+            }
+        }
+
+        fn get_erc20(&self, token: Token) -> Result<Erc20Ref> {
+            if !self.tokens.contains(token) {
+                return Err(Error::TokenNotSupported(token));
+            }
+            Ok(Erc20Ref::from_account_id(token))
+        }
+
+        fn ensure_not_paused(&self) -> Result<()> {
+            if self.paused {
+                return Err(Error::TradingPaused);
+            }
+            Ok(())
+        }
+
+        // the books may hold entries of an older layout until `migrate` is done
+        fn ensure_migrated(&self) -> Result<()> {
+            if self.version() < STORAGE_VERSION {
+                return Err(Error::MigrationPending);
+            }
+            Ok(())
+        }
+
+        /// Returns the current owner.
+        #[ink(message)]
+        pub fn owner(&self) -> AccountId {
+            self.owner
+        }
+
+        /// Returns the proposed owner, if an ownership transfer is pending.
+        #[ink(message)]
+        pub fn pending_owner(&self) -> Option<AccountId> {
+            self.pending_owner
+        }
+
+        /// Proposes a new owner, only callable by the owner.
+        ///
+        /// The transfer completes once the new owner calls `accept_ownership`; proposing
+        /// again replaces the pending owner.
+        ///
+        /// # Arguments
+        /// * `new_owner` - The account to hand ownership over to
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if proposed, Error if unauthorized
+        #[ink(message)]
+        pub fn transfer_ownership(&mut self, new_owner: AccountId) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+
+            self.pending_owner = Some(new_owner);
+
+            self.env().emit_event(OwnershipTransferStarted {
+                previous_owner: self.owner,
+                new_owner,
+            });
+
+            Ok(())
+        }
+
+        /// Accepts a pending ownership transfer, only callable by the proposed owner.
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if ownership is taken over, Error if the caller is not the pending owner
+        #[ink(message)]
+        pub fn accept_ownership(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            if self.pending_owner != Some(caller) {
+                return Err(Error::NotPendingOwner);
+            }
+
+            let previous_owner = self.owner;
+            self.owner = caller;
+            self.pending_owner = None;
+
+            self.env().emit_event(OwnershipTransferred {
+                previous_owner,
+                new_owner: caller,
+            });
+
+            Ok(())
+        }
+
+        /// Checks whether trading is paused.
+        #[ink(message)]
+        pub fn is_paused(&self) -> bool {
+            self.paused
+        }
+
+        /// Pauses order placement, only callable by the owner.
+        ///
+        /// Cancels, withdrawals and sweeping expired orders stay available.
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if paused, Error if unauthorized
+        #[ink(message)]
+        pub fn pause(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::NotOwner);
+            }
+
+            self.paused = true;
+
+            self.env().emit_event(Paused { account: caller });
+
+            Ok(())
+        }
+
+        /// Resumes order placement, only callable by the owner.
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if unpaused, Error if unauthorized
+        #[ink(message)]
+        pub fn unpause(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::NotOwner);
+            }
+
+            self.paused = false;
+
+            self.env().emit_event(Unpaused { account: caller });
+
+            Ok(())
+        }
+
+        /// Returns the layout version of the data in storage.
+        #[ink(message)]
+        pub fn version(&self) -> u32 {
+            self.version.get().unwrap_or_default()
+        }
+
+        /// Replaces the contract code in place, only callable by the owner.
+        ///
+        /// Storage is kept as is. If the new code changes the storage layout, call
+        /// `migrate` on the new code right after to bring the data up to date.
+        ///
+        /// # Arguments
+        /// * `code_hash` - The hash of the uploaded new contract code
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if the code is replaced, Error if unauthorized or the code hash is unknown
+        #[ink(message)]
+        pub fn upgrade(&mut self, code_hash: Hash) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+
+            self.env()
+                .set_code_hash(&code_hash)
+                .map_err(|_| Error::UpgradeFailed)?;
+
+            self.env().emit_event(Upgraded { code_hash });
+
+            Ok(())
+        }
+
+        /// Migrates storage written by an older code version, only callable by the owner.
+        ///
+        /// Steps are applied one version at a time up to `STORAGE_VERSION`. Layout changes
+        /// must keep the root fields decodable, e.g. by putting new data behind a `Mapping`
+        /// or `Lazy`, and convert old entries here. The order book cannot be used until the
+        /// migration is done.
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if migrated, Error if unauthorized or storage is already up to date
+        #[ink(message)]
+        pub fn migrate(&mut self) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+            let from_version = self.version();
+            if from_version >= STORAGE_VERSION {
+                return Err(Error::AlreadyMigrated);
+            }
+
+            let mut version = from_version;
+            while version < STORAGE_VERSION {
+                // a step per layout change goes here, keyed by `version`
+                version = version.checked_add(1).ok_or(Error::Overflow)?;
             }
+            self.version.set(&version);
+
+            self.env().emit_event(Migrated {
+                from_version,
+                to_version: version,
+            });
+
+            Ok(())
+        }
+
+        /// Lists a new market, only callable by the owner.
+        ///
+        /// # Arguments
+        /// * `base` - The address of the base token contract
+        /// * `quote` - The address of the quote token contract
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if the market is listed, Error if unauthorized or the pair is invalid
+        #[ink(message)]
+        pub fn add_market(&mut self, base: Token, quote: Token) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+            self.ensure_migrated()?;
+
+            self.order_book.add_market((base, quote))?;
+            self.tokens.insert(base, &());
+            self.tokens.insert(quote, &());
+
+            self.env().emit_event(MarketAdded { base, quote });
+
+            Ok(())
+        }
+
+        /// Checks whether a market is listed.
+        ///
+        /// # Arguments
+        /// * `base` - The address of the base token contract
+        /// * `quote` - The address of the quote token contract
+        ///
+        /// # Returns
+        /// * `bool` - True if the market is listed
+        #[ink(message)]
+        pub fn has_market(&self, base: Token, quote: Token) -> bool {
+            self.order_book.has_market((base, quote))
+        }
+
+        /// Sets the maker and taker fee rates, only callable by the owner.
+        ///
+        /// # Arguments
+        /// * `maker_fee_bps` - The fee rate of resting orders, in basis points
+        /// * `taker_fee_bps` - The fee rate of incoming orders, in basis points
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if the rates are set, Error if unauthorized or a rate exceeds 10000
+        #[ink(message)]
+        pub fn set_fee_rates(&mut self, maker_fee_bps: u16, taker_fee_bps: u16) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+
+            self.order_book
+                .set_fee_rates(maker_fee_bps, taker_fee_bps)?;
+
+            self.env().emit_event(FeeRatesUpdated {
+                maker_fee_bps,
+                taker_fee_bps,
+            });
+
+            Ok(())
+        }
+
+        /// Returns the (maker, taker) fee rates in basis points.
+        #[ink(message)]
+        pub fn fee_rates(&self) -> (u16, u16) {
+            self.order_book.fee_rates()
         }
 
-        fn get_erc20(&mut self, token: Token) -> &mut Erc20Ref {
-            match token {
-                Token::Base => &mut self.base_token_contract,
-                Token::Quote => &mut self.quote_token_contract,
+        /// Returns the accrued fees of a token.
+        ///
+        /// # Arguments
+        /// * `token` - The address of the token contract
+        ///
+        /// # Returns
+        /// * `u128` - The fees not yet withdrawn
+        #[ink(message)]
+        pub fn fees_of(&self, token: Token) -> u128 {
+            self.vault.get_fees(token)
+        }
+
+        /// Withdraws accrued fees to the owner, only callable by the owner.
+        ///
+        /// # Arguments
+        /// * `token` - The address of the token contract to withdraw
+        /// * `amount` - The amount of fees to withdraw
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if withdrawal successful, Error if unauthorized or insufficient fees
+        #[ink(message)]
+        pub fn withdraw_fees(&mut self, token: Token, amount: u128) -> Result<()> {
+            let caller = self.env().caller();
+            if caller != self.owner {
+                return Err(Error::NotOwner);
+            }
+            if amount == 0 {
+                return Err(Error::ZeroAmount);
             }
+
+            let mut erc20 = self.get_erc20(token)?;
+            self.vault.withdraw_fees(token, amount)?;
+            erc20
+                .transfer(caller, amount)
+                .map_err(|e| Error::Erc20(token, e))?;
+
+            self.env().emit_event(FeesWithdrawn { token, amount });
+
+            Ok(())
         }
 
         /// Deposits tokens into the DEX.
         ///
         /// # Arguments
-        /// * `token` - The type of token to deposit (Base or Quote)
+        /// * `token` - The address of the token contract to deposit
         /// * `amount` - The amount of tokens to deposit
         ///
         /// # Returns
@@ -135,24 +678,23 @@ pub mod minidex {
         #[ink(message)]
         pub fn deposit(&mut self, token: Token, amount: u128) -> Result<()> {
             if amount == 0 {
-                return Err(Error::InvalidQuantity(
-                    "Deposit amount cannot be zero".into(),
-                ));
+                return Err(Error::ZeroAmount);
             }
 
             let caller = self.env().caller();
             let contract = self.env().account_id();
+            let mut erc20 = self.get_erc20(token)?;
             // check if user has approved enough tokens
-            let allowance = self.get_erc20(token).allowance(caller, contract);
+            let allowance = erc20.allowance(caller, contract);
             if allowance < amount {
                 return Err(Error::InsufficientAllowance(token));
             }
             // update vault balance
-            self.vault.deposit(caller, token, amount);
+            self.vault.deposit(caller, token, amount)?;
             // transfer tokens from user to contract
-            self.get_erc20(token)
+            erc20
                 .transfer_from(caller, contract, amount)
-                .map_err(|_| Error::InsufficientToken(token))?;
+                .map_err(|e| Error::Erc20(token, e))?;
 
             self.env().emit_event(Deposit {
                 account: caller,
@@ -166,7 +708,7 @@ pub mod minidex {
         /// Withdraws tokens from the DEX.
         ///
         /// # Arguments
-        /// * `token` - The type of token to withdraw (Base or Quote)
+        /// * `token` - The address of the token contract to withdraw
         /// * `amount` - The amount of tokens to withdraw
         ///
         /// # Returns
@@ -174,18 +716,17 @@ pub mod minidex {
         #[ink(message)]
         pub fn withdraw(&mut self, token: Token, amount: u128) -> Result<()> {
             if amount == 0 {
-                return Err(Error::InvalidQuantity(
-                    "Withdrawal amount cannot be zero".into(),
-                ));
+                return Err(Error::ZeroAmount);
             }
 
             let caller = self.env().caller();
+            let mut erc20 = self.get_erc20(token)?;
             // check and update vault balance
             self.vault.withdraw(caller, token, amount)?;
             // transfer tokens from contract to user
-            self.get_erc20(token)
+            erc20
                 .transfer(caller, amount)
-                .map_err(|_| Error::InsufficientToken(token))?;
+                .map_err(|e| Error::Erc20(token, e))?;
 
             self.env().emit_event(Withdraw {
                 account: caller,
@@ -199,7 +740,7 @@ pub mod minidex {
         /// Returns the balance of tokens for the caller.
         ///
         /// # Arguments
-        /// * `token` - The type of token to check balance for (Base or Quote)
+        /// * `token` - The address of the token contract to check balance for
         ///
         /// # Returns
         /// * `u128` - The balance of the specified token
@@ -211,7 +752,7 @@ pub mod minidex {
         /// Returns the locked amount of tokens for the caller.
         ///
         /// # Arguments
-        /// * `token` - The type of token to check locked amount for (Base or Quote)
+        /// * `token` - The address of the token contract to check locked amount for
         ///
         /// # Returns
         /// * `u128` - The locked amount of the specified token
@@ -220,84 +761,670 @@ pub mod minidex {
             self.vault.get_locked(self.env().caller(), token)
         }
 
-        /// Places a new limit order in the DEX.
+        /// Returns the best bid/ask and the aggregated depth of a market.
         ///
         /// # Arguments
-        /// * `pair` - The trading pair (Base, Quote)
-        /// * `side` - The order side (Buy or Sell)
-        /// * `price` - The order price
-        /// * `qty` - The order quantity
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        /// * `levels` - The maximum number of price levels per side
+        ///
+        /// # Returns
+        /// * `Result<Depth>` - The market depth, Error if the pair is not listed
+        #[ink(message)]
+        pub fn get_depth(&self, pair: Pair, levels: u32) -> Result<Depth> {
+            self.ensure_migrated()?;
+            let now = self.env().block_timestamp();
+            self.order_book.depth(pair, levels, now)
+        }
+
+        /// Returns the best bid/ask of a market and the spread between them.
+        ///
+        /// Cheaper than `get_depth`, but may include expired orders not swept yet.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        ///
+        /// # Returns
+        /// * `Result<Spread>` - The best prices and spread, Error if the pair is not listed
+        #[ink(message)]
+        pub fn get_spread(&self, pair: Pair) -> Result<Spread> {
+            self.ensure_migrated()?;
+            self.order_book.spread(pair)
+        }
+
+        /// Returns an open order by its ID.
+        ///
+        /// # Arguments
+        /// * `order_id` - The ID of the order
+        ///
+        /// # Returns
+        /// * `Result<Option<Order>>` - The order with its remaining qty and locked amount, None if filled, cancelled or expired
+        #[ink(message)]
+        pub fn get_order(&self, order_id: u64) -> Result<Option<Order>> {
+            self.ensure_migrated()?;
+            Ok(self.order_book.get_order(order_id))
+        }
+
+        /// Returns a stop order that has not triggered yet by its ID.
+        ///
+        /// # Arguments
+        /// * `stop_id` - The ID of the stop order
+        ///
+        /// # Returns
+        /// * `Result<Option<StopOrder>>` - The stop order, None if triggered or cancelled
+        #[ink(message)]
+        pub fn get_stop_order(&self, stop_id: u64) -> Result<Option<StopOrder>> {
+            self.ensure_migrated()?;
+            Ok(self.trigger_book.get_stop_order(stop_id))
+        }
+
+        /// Returns the price of the latest trade of a market, which fires stop orders.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        ///
+        /// # Returns
+        /// * `Result<Option<u128>>` - The last trade price, None if the market never traded
+        #[ink(message)]
+        pub fn last_price(&self, pair: Pair) -> Result<Option<u128>> {
+            self.ensure_migrated()?;
+            Ok(self.trigger_book.last_price(pair))
+        }
+
+        /// Returns the ID of the order an account placed under a client order id.
+        ///
+        /// Lets a submitter that lost the response of `place_limit_order` check whether
+        /// the order went through.
+        ///
+        /// # Arguments
+        /// * `account` - The owner of the order
+        /// * `client_order_id` - The id chosen by the owner
+        ///
+        /// # Returns
+        /// * `Result<Option<u64>>` - The ID of the order, None if the client order id was never used
+        #[ink(message)]
+        pub fn order_id_of_client(
+            &self,
+            account: AccountId,
+            client_order_id: u64,
+        ) -> Result<Option<u64>> {
+            self.ensure_migrated()?;
+            Ok(self.order_book.order_id_of_client(account, client_order_id))
+        }
+
+        /// Returns a page of the open orders of an account, in ascending ID order.
+        ///
+        /// # Arguments
+        /// * `account` - The owner of the orders
+        /// * `offset` - The number of orders to skip
+        /// * `limit` - The maximum number of orders to return
+        ///
+        /// # Returns
+        /// * `Result<Vec<Order>>` - The open orders of the account
+        #[ink(message)]
+        pub fn open_orders_of(
+            &self,
+            account: AccountId,
+            offset: u32,
+            limit: u32,
+        ) -> Result<Vec<Order>> {
+            self.ensure_migrated()?;
+            Ok(self.order_book.open_orders_of(account, offset, limit))
+        }
+
+        /// Places a new limit order in the DEX.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        /// * `side` - The order side (Buy or Sell)
+        /// * `price` - The order price
+        /// * `qty` - The order quantity
+        /// * `options` - The order flags, e.g. time in force
         ///
         /// # Returns
         /// * `Result<u64>` - The order ID if successful, Error if order placement fails
         #[ink(message)]
         pub fn place_limit_order(
             &mut self,
-            pair: (Token, Token),
+            pair: Pair,
             side: Side,
             price: u128,
             qty: u128,
+            options: OrderOptions,
         ) -> Result<u64> {
+            let order = self.open_limit_order(pair, side, price, qty, options)?;
+            self.run_limit_order(order, options)
+        }
+
+        /// Checks a new limit order and locks its funds.
+        ///
+        /// On failure nothing but the allocated order ID has changed, so a batch may skip
+        /// the order and go on.
+        fn open_limit_order(
+            &mut self,
+            pair: Pair,
+            side: Side,
+            price: u128,
+            qty: u128,
+            options: OrderOptions,
+        ) -> Result<Order> {
+            self.ensure_not_paused()?;
+            self.ensure_migrated()?;
+
             // sanity check
-            if pair != (Token::Base, Token::Quote) {
-                return Err(Error::InvalidOrder("Order dex pair not supported".into()));
+            if !self.order_book.has_market(pair) {
+                return Err(Error::PairNotSupported);
             }
             if price == 0 {
-                return Err(Error::InvalidPrice("Order price cannot be zero".into()));
+                return Err(Error::ZeroPrice);
             }
             if qty == 0 {
-                return Err(Error::InvalidQuantity(
-                    "Order quantity cannot be zero".into(),
-                ));
+                return Err(Error::ZeroQuantity);
             }
+            let required = match side {
+                Side::Buy => price.checked_mul(qty).ok_or(Error::Overflow)?,
+                Side::Sell => qty,
+            };
 
             let caller = self.env().caller();
             let now = self.env().block_timestamp();
+            if options.max_fills == Some(0) {
+                return Err(Error::ZeroMaxFills);
+            }
+            if options.display_qty == Some(0) {
+                return Err(Error::ZeroDisplayQuantity);
+            }
+            if options.expires_at.is_some_and(|t| t <= now) {
+                return Err(Error::ExpiryInPast);
+            }
+            if options
+                .client_order_id
+                .is_some_and(|id| self.order_book.order_id_of_client(caller, id).is_some())
+            {
+                return Err(Error::ClientOrderIdUsed);
+            }
             let mut order = self
                 .order_book
-                .make_new_order(caller, pair, side, price, qty, now);
+                .make_new_order(caller, pair, side, price, qty, now)?;
+            order.expires_at = options.expires_at;
+            order.time_in_force = options.time_in_force;
+            order.max_fills = options.max_fills;
+            order.stp = options.stp;
+            order.client_order_id = options.client_order_id;
+            order.display_qty = options.display_qty;
+
+            // time in force checks, before any funds move
+            match options.time_in_force {
+                TimeInForce::PostOnly if self.order_book.would_cross(&order)? => {
+                    return Err(Error::PostOnlyWouldCross);
+                }
+                TimeInForce::FillOrKill if self.order_book.fillable_qty(&order)? < qty => {
+                    return Err(Error::FillOrKillNotFilled);
+                }
+                _ => {}
+            }
+
+            self.lock_funds(&mut order, required)?;
+            Ok(order)
+        }
 
-            // emit
+        /// Matches a limit order from `open_limit_order`, then rests, parks or drops what
+        /// is left.
+        ///
+        /// An error here leaves the book half updated, so callers must pass it on to revert.
+        fn run_limit_order(&mut self, order: Order, options: OrderOptions) -> Result<u64> {
+            let (pair, order_id, owner, qty) = (order.pair, order.id, order.owner, order.qty);
+            let (res, _) = self.submit(order, options.max_fills, MarketAmount::Base(qty))?;
+            if let Some(client_order_id) = options.client_order_id {
+                self.order_book
+                    .reserve_client_order_id(owner, client_order_id, order_id)?;
+            }
+
+            if let Some(order) = res {
+                // stopped by max fills while the book still crosses
+                let capped = options.max_fills.is_some() && self.order_book.would_cross(&order)?;
+                match options.time_in_force {
+                    TimeInForce::FillOrKill if capped => return Err(Error::FillOrKillMaxFills),
+                    TimeInForce::GoodTillCancel | TimeInForce::PostOnly if capped => {
+                        self.order_book.park_order(order)?;
+                        self.env().emit_event(OrderPending { order_id });
+                    }
+                    TimeInForce::GoodTillCancel | TimeInForce::PostOnly => {
+                        self.order_book.insert_new_order(order)?
+                    }
+                    TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => {
+                        self.discard(order)?
+                    }
+                }
+            }
+            self.fire_stops(pair)?;
+
+            Ok(order_id)
+        }
+
+        /// Places a new market order in the DEX, which is never rested on the book.
+        ///
+        /// Buys may be sized by base quantity or by quote amount to spend, sells only by
+        /// base quantity. Matching stops once the order is filled, the quote amount is
+        /// spent, the next level is beyond `worst_price` or `max_fills` maker orders are
+        /// matched; anything left is unlocked.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        /// * `side` - The order side (Buy or Sell)
+        /// * `amount` - The base quantity or quote amount to trade
+        /// * `worst_price` - The worst acceptable price, required unless buying by quote amount
+        /// * `max_fills` - The maximum number of maker orders to match, `None` for no limit
+        ///
+        /// # Returns
+        /// * `Result<MarketFill>` - The filled amounts if successful, Error if order placement fails
+        #[ink(message)]
+        pub fn place_market_order(
+            &mut self,
+            pair: Pair,
+            side: Side,
+            amount: MarketAmount,
+            worst_price: Option<u128>,
+            max_fills: Option<u32>,
+        ) -> Result<MarketFill> {
+            self.ensure_not_paused()?;
+            self.ensure_migrated()?;
+
+            // sanity check
+            if !self.order_book.has_market(pair) {
+                return Err(Error::PairNotSupported);
+            }
+            if worst_price == Some(0) {
+                return Err(Error::ZeroPrice);
+            }
+            if max_fills == Some(0) {
+                return Err(Error::ZeroMaxFills);
+            }
+            let (qty, required) = match (side, amount, worst_price) {
+                (_, MarketAmount::Base(0) | MarketAmount::Quote(0), _) => {
+                    return Err(Error::ZeroQuantity);
+                }
+                (Side::Buy, MarketAmount::Quote(amt), _) => (u128::MAX, amt),
+                (Side::Buy, MarketAmount::Base(qty), Some(price)) => {
+                    (qty, price.checked_mul(qty).ok_or(Error::Overflow)?)
+                }
+                (Side::Sell, MarketAmount::Base(qty), Some(_)) => (qty, qty),
+                (Side::Sell, MarketAmount::Quote(_), _) => {
+                    return Err(Error::MarketSellByQuote);
+                }
+                (_, MarketAmount::Base(_), None) => {
+                    return Err(Error::WorstPriceRequired);
+                }
+            };
+            // only buys by quote amount go without a price limit
+            let price = worst_price.unwrap_or(u128::MAX);
+
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let mut order = self
+                .order_book
+                .make_new_order(caller, pair, side, price, qty, now)?;
             let order_id = order.id;
+
+            self.lock_funds(&mut order, required)?;
+            let (res, evts) = self.submit(order, max_fills, amount)?;
+
+            // never rest, release whatever is left
+            if let Some(order) = res {
+                self.discard(order)?;
+            }
+            self.fire_stops(pair)?;
+
+            let mut fill = MarketFill {
+                order_id,
+                ..Default::default()
+            };
+            let fills = evts.iter().filter_map(|e| match e {
+                BookEvent::Filled(e) if e.order_id == order_id => Some(e),
+                _ => None,
+            });
+            for e in fills {
+                let quote_amt = e
+                    .filled_price
+                    .checked_mul(e.filled_qty)
+                    .ok_or(Error::Overflow)?;
+                fill.filled_qty = fill
+                    .filled_qty
+                    .checked_add(e.filled_qty)
+                    .ok_or(Error::Overflow)?;
+                fill.quote_amount = fill
+                    .quote_amount
+                    .checked_add(quote_amt)
+                    .ok_or(Error::Overflow)?;
+                fill.fee = fill.fee.checked_add(e.fee).ok_or(Error::Overflow)?;
+            }
+            fill.avg_price = fill
+                .quote_amount
+                .checked_div(fill.filled_qty)
+                .unwrap_or_default();
+
+            Ok(fill)
+        }
+
+        /// Locks the funds of a new order, changing nothing if they cannot be locked.
+        fn lock_funds(&mut self, order: &mut Order, required: u128) -> Result<()> {
+            let (base, quote) = order.pair;
+            let token = match order.side {
+                Side::Buy => quote,
+                Side::Sell => base,
+            };
+            self.vault.lock(order.owner, token, required)?;
+            order.locked = required;
+            Ok(())
+        }
+
+        /// Emits a new order with its funds locked and matches it against the book.
+        ///
+        /// `amount` is what the order was sized by; a buy sized by quote amount is matched
+        /// with an unbounded `qty` and reports the quote it has left instead. An error from
+        /// matching leaves the book half updated, so callers must pass it on to revert.
+        /// Returns the unfilled remainder (if any) and the book events already emitted.
+        fn submit(
+            &mut self,
+            order: Order,
+            max_fills: Option<u32>,
+            amount: MarketAmount,
+        ) -> Result<(Option<Order>, Vec<BookEvent>)> {
+            let (pair, order_id) = (order.pair, order.id);
+            let price = match amount {
+                // no worst price, the order takes any price
+                MarketAmount::Quote(_) if order.price == u128::MAX => None,
+                _ => Some(order.price),
+            };
             self.env().emit_event(NewOrder {
                 order_id,
+                owner: order.owner,
+                pair,
+                side: order.side,
                 price,
-                qty,
+                amount,
+                client_order_id: order.client_order_id,
             });
 
-            // lock & try match
-            let (base, quote) = pair;
-            let (res, evts) = match side {
+            let (res, evts) = match order.side {
                 Side::Buy => {
-                    let required = price.checked_mul(qty).unwrap();
-                    self.vault.lock(caller, quote, required)?;
-                    order.locked = required;
-
-                    // assert ok: transfer lock always success
                     self.order_book
-                        .match_sell_orders(order, &mut self.vault)
-                        .unwrap()
+                        .match_sell_orders(order, max_fills, &mut self.vault)?
                 }
                 Side::Sell => {
-                    self.vault.lock(caller, base, qty)?;
-                    order.locked = qty;
-
-                    // assert ok: transfer lock always success
                     self.order_book
-                        .match_buy_orders(order, &mut self.vault)
-                        .unwrap()
+                        .match_buy_orders(order, max_fills, &mut self.vault)?
                 }
             };
 
+            match amount {
+                MarketAmount::Base(_) => self.emit_book_events(&evts),
+                MarketAmount::Quote(budget) => self.emit_budget_events(order_id, budget, &evts)?,
+            }
+            self.record_last_price(pair, &evts);
+
+            Ok((res, evts))
+        }
+
+        // the last fill of a match is the market's last trade price
+        fn record_last_price(&mut self, pair: Pair, evts: &[BookEvent]) {
+            let last_fill = evts.iter().rev().find_map(|e| match e {
+                BookEvent::Filled(e) => Some(e.filled_price),
+                _ => None,
+            });
+            if let Some(price) = last_fill {
+                self.trigger_book.set_last_price(pair, price);
+            }
+        }
+
+        /// Turns the stop orders triggered by the last trade price of a market into orders.
+        ///
+        /// Trades of a triggered order may trigger further stops, at most
+        /// `MAX_STOP_TRIGGERS` fire per call. Each order matches at most `MAX_STOP_FILLS`
+        /// maker orders here, so the caller's call stays bounded; one stopped by them
+        /// while the book still crosses waits for the crank.
+        fn fire_stops(&mut self, pair: Pair) -> Result<()> {
+            let now = self.env().block_timestamp();
+            for _ in 0..MAX_STOP_TRIGGERS {
+                let Some(stop) = self.trigger_book.take_triggered(pair) else {
+                    break;
+                };
+                let mut order = self
+                    .order_book
+                    .make_new_order(stop.owner, pair, stop.side, stop.price, stop.qty, now)?;
+                order.locked = stop.locked;
+                if stop.kind == StopKind::Market {
+                    order.time_in_force = TimeInForce::ImmediateOrCancel;
+                }
+
+                self.env().emit_event(StopTriggered {
+                    stop_id: stop.id,
+                    order_id: order.id,
+                    last_price: self.trigger_book.last_price(pair).unwrap_or_default(),
+                });
+
+                let (res, _) =
+                    self.submit(order, Some(MAX_STOP_FILLS), MarketAmount::Base(stop.qty))?;
+                let Some(order) = res else {
+                    continue;
+                };
+                if self.order_book.would_cross(&order)? {
+                    // stopped by max fills while the book still crosses
+                    let order_id = order.id;
+                    self.order_book.park_order(order)?;
+                    self.env().emit_event(OrderPending { order_id });
+                    continue;
+                }
+                match stop.kind {
+                    StopKind::Limit => self.order_book.insert_new_order(order)?,
+                    StopKind::Market => self.discard(order)?,
+                }
+            }
+            Ok(())
+        }
+
+        fn emit_book_events(&self, evts: &[BookEvent]) {
             for e in evts {
-                self.env().emit_event(OrderFilled::from(e));
+                self.emit_book_event(e);
             }
+        }
 
-            if let Some(order) = res {
-                self.order_book.insert_new_order(order);
+        // as `emit_book_events`, the fills of a buy sized by quote amount report the
+        // quote it has left to spend
+        fn emit_budget_events(
+            &self,
+            order_id: u64,
+            mut budget: u128,
+            evts: &[BookEvent],
+        ) -> Result<()> {
+            for e in evts {
+                match *e {
+                    BookEvent::Filled(e) if e.order_id == order_id => {
+                        let quote_amt = e
+                            .filled_price
+                            .checked_mul(e.filled_qty)
+                            .ok_or(Error::Overflow)?;
+                        budget = budget.checked_sub(quote_amt).ok_or(Error::Overflow)?;
+                        self.env().emit_event(OrderFilled {
+                            remaining: MarketAmount::Quote(budget),
+                            ..OrderFilled::from(e)
+                        });
+                    }
+                    _ => self.emit_book_event(e),
+                }
             }
+            Ok(())
+        }
 
-            Ok(order_id)
+        fn emit_book_event(&self, e: &BookEvent) {
+            match *e {
+                BookEvent::Filled(e) => self.env().emit_event(OrderFilled::from(e)),
+                BookEvent::Expired(e) => self.env().emit_event(OrderExpired::from(e)),
+                BookEvent::Cancelled(e) => self.env().emit_event(OrderCancelled {
+                    order_id: e.order_id,
+                }),
+                BookEvent::Trade(e) => self.env().emit_event(Trade::from(e)),
+            }
+        }
+
+        /// Drops the unfilled remainder of an order that must not rest on the book.
+        fn discard(&mut self, order: Order) -> Result<()> {
+            let order_id = order.id;
+            self.order_book.discard_order(order, &mut self.vault)?;
+
+            self.env().emit_event(OrderCancelled { order_id });
+
+            Ok(())
+        }
+
+        /// Places a stop order, which waits off the book until the last trade price of
+        /// the market meets its trigger.
+        ///
+        /// Funds are locked at placement as for a limit order at `price`. Once triggered,
+        /// a stop-limit order is matched and rests as a good-till-cancel limit order; a
+        /// stop-market order is matched no worse than `price` and its remainder unlocked.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        /// * `side` - The order side (Buy or Sell)
+        /// * `kind` - Whether to place a limit or a market order once triggered
+        /// * `trigger` - The last trade price condition that fires the order
+        /// * `price` - The limit price, or the worst price of a stop-market order
+        /// * `qty` - The quantity of the order
+        ///
+        /// # Returns
+        /// * `Result<u64>` - The stop order ID if successful, Error if the trigger is already met or placement fails
+        #[ink(message)]
+        pub fn place_stop_order(
+            &mut self,
+            pair: Pair,
+            side: Side,
+            kind: StopKind,
+            trigger: Trigger,
+            price: u128,
+            qty: u128,
+        ) -> Result<u64> {
+            self.ensure_not_paused()?;
+            self.ensure_migrated()?;
+
+            // sanity check
+            if !self.order_book.has_market(pair) {
+                return Err(Error::PairNotSupported);
+            }
+            if price == 0 || matches!(trigger, Trigger::AtOrBelow(0) | Trigger::AtOrAbove(0)) {
+                return Err(Error::ZeroPrice);
+            }
+            if qty == 0 {
+                return Err(Error::ZeroQuantity);
+            }
+
+            let caller = self.env().caller();
+            let (base, quote) = pair;
+            let (token, locked) = match side {
+                Side::Buy => (quote, price.checked_mul(qty).ok_or(Error::Overflow)?),
+                Side::Sell => (base, qty),
+            };
+            self.vault.lock(caller, token, locked)?;
+
+            let stop_id = self.trigger_book.insert_stop_order(StopOrder {
+                id: Default::default(),
+                owner: caller,
+                pair,
+                side,
+                kind,
+                trigger,
+                price,
+                qty,
+                timestamp: self.env().block_timestamp(),
+                locked,
+            })?;
+
+            self.env().emit_event(NewStopOrder {
+                stop_id,
+                trigger,
+                price,
+                qty,
+            });
+
+            Ok(stop_id)
+        }
+
+        /// Cancels a stop order that has not triggered yet.
+        ///
+        /// # Arguments
+        /// * `stop_id` - The ID of the stop order to cancel
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if cancellation successful, Error if the stop order is not found or not owned
+        #[ink(message)]
+        pub fn cancel_stop_order(&mut self, stop_id: u64) -> Result<()> {
+            self.ensure_migrated()?;
+
+            let caller = self.env().caller();
+            self.trigger_book
+                .cancel_stop_order(caller, stop_id, &mut self.vault)?;
+
+            self.env().emit_event(StopCancelled { stop_id });
+
+            Ok(())
+        }
+
+        /// Places and cancels orders in one call.
+        ///
+        /// Operations run in order, as if sent one by one. In atomic mode the first failing
+        /// operation fails the whole batch and nothing is applied; otherwise operations
+        /// failing their checks, before any funds move, are skipped and reported in place.
+        /// An error once an operation changes the book, e.g. while matching, always fails
+        /// the whole batch.
+        ///
+        /// # Arguments
+        /// * `ops` - The placements and cancellations to run
+        /// * `atomic` - Whether all operations must succeed
+        ///
+        /// # Returns
+        /// * `Result<Vec<Result<u64>>>` - Per operation, the placed or cancelled order ID or its error
+        #[ink(message)]
+        pub fn batch(&mut self, ops: Vec<BatchOp>, atomic: bool) -> Result<Vec<Result<u64>>> {
+            let mut results = Vec::with_capacity(ops.len());
+            for op in ops {
+                // only failed checks are skipped, later errors revert the whole call
+                let res = match op {
+                    BatchOp::Place {
+                        pair,
+                        side,
+                        price,
+                        qty,
+                        options,
+                    } => match self.open_limit_order(pair, side, price, qty, options) {
+                        Ok(order) => Ok(self.run_limit_order(order, options)?),
+                        Err(e) => Err(e),
+                    },
+                    BatchOp::Cancel { order_id } => match self.check_cancel(order_id) {
+                        Ok(()) => {
+                            self.cancel_order(order_id)?;
+                            Ok(order_id)
+                        }
+                        Err(e) => Err(e),
+                    },
+                };
+                match res {
+                    Err(e) if atomic => return Err(e),
+                    res => results.push(res),
+                }
+            }
+            Ok(results)
+        }
+
+        /// Checks that the caller may cancel an order, changing nothing.
+        fn check_cancel(&self, order_id: u64) -> Result<()> {
+            self.ensure_migrated()?;
+            let order = self
+                .order_book
+                .get_order(order_id)
+                .ok_or(Error::OrderNotFound(order_id))?;
+            if order.owner != self.env().caller() {
+                return Err(Error::NotOrderOwner);
+            }
+            Ok(())
         }
 
         /// Cancels an existing order.
@@ -309,6 +1436,8 @@ pub mod minidex {
         /// * `Result<()>` - Ok if cancellation successful, Error if cancellation fails
         #[ink(message)]
         pub fn cancel_order(&mut self, order_id: u64) -> Result<()> {
+            self.ensure_migrated()?;
+
             let caller = self.env().caller();
             self.order_book
                 .cancel_order(caller, order_id, &mut self.vault)?;
@@ -317,6 +1446,157 @@ pub mod minidex {
 
             Ok(())
         }
+
+        /// Cancels an open order of the caller by its client order id.
+        ///
+        /// # Arguments
+        /// * `client_order_id` - The id the order was placed with
+        ///
+        /// # Returns
+        /// * `Result<u64>` - The ID of the cancelled order, Error if the client order id is unknown or the order is no longer open
+        #[ink(message)]
+        pub fn cancel_by_client_id(&mut self, client_order_id: u64) -> Result<u64> {
+            let caller = self.env().caller();
+            let order_id = self
+                .order_book
+                .order_id_of_client(caller, client_order_id)
+                .ok_or(Error::ClientOrderIdNotFound(client_order_id))?;
+            self.cancel_order(order_id)?;
+            Ok(order_id)
+        }
+
+        /// Changes the price or quantity of an open order in place.
+        ///
+        /// Reducing the quantity at the same price keeps the order's time priority; any
+        /// other change re-queues it at the new price, where it may match right away up to
+        /// its max fills, the crank matching the rest. Post-only orders may not move to a
+        /// price that matches. Locked tokens are topped up or released for the difference.
+        ///
+        /// # Arguments
+        /// * `order_id` - The ID of the order to modify
+        /// * `price` - The new price
+        /// * `qty` - The new unfilled quantity
+        ///
+        /// # Returns
+        /// * `Result<()>` - Ok if modified, Error if the order is not found, not owned, a post-only order would match or funds are insufficient
+        #[ink(message)]
+        pub fn modify_order(&mut self, order_id: u64, price: u128, qty: u128) -> Result<()> {
+            self.ensure_not_paused()?;
+            self.ensure_migrated()?;
+
+            if price == 0 {
+                return Err(Error::ZeroPrice);
+            }
+            if qty == 0 {
+                return Err(Error::ZeroQuantity);
+            }
+
+            let caller = self.env().caller();
+            let now = self.env().block_timestamp();
+            let pair = self
+                .order_book
+                .get_order(order_id)
+                .ok_or(Error::OrderNotFound(order_id))?
+                .pair;
+            let (res, evts) =
+                self.order_book
+                    .modify_order(caller, order_id, price, qty, now, &mut self.vault)?;
+
+            self.env().emit_event(OrderModified {
+                order_id,
+                price,
+                qty: res.as_ref().map_or(0, |order| order.qty),
+            });
+            self.emit_book_events(&evts);
+            if let Some(order) = res {
+                // stopped by max fills while the book still crosses
+                if order.max_fills.is_some() && self.order_book.would_cross(&order)? {
+                    self.env().emit_event(OrderPending { order_id });
+                }
+            }
+            self.record_last_price(pair, &evts);
+            self.fire_stops(pair)?;
+
+            Ok(())
+        }
+
+        /// Cancels the caller's open orders, oldest first.
+        ///
+        /// Call again until it returns less than `limit` to cancel everything.
+        ///
+        /// # Arguments
+        /// * `side` - Only cancel orders of this side, `None` for both sides
+        /// * `limit` - The maximum number of orders to cancel in this call
+        ///
+        /// # Returns
+        /// * `Result<u32>` - The number of cancelled orders
+        #[ink(message)]
+        pub fn cancel_all_orders(&mut self, side: Option<Side>, limit: u32) -> Result<u32> {
+            self.ensure_migrated()?;
+
+            let caller = self.env().caller();
+            let cancelled =
+                self.order_book
+                    .cancel_all_orders(caller, side, limit, &mut self.vault)?;
+
+            for &order_id in &cancelled {
+                self.env().emit_event(OrderCancelled { order_id });
+            }
+
+            Ok(cancelled.len() as u32)
+        }
+
+        /// Continues matching the pending orders of a market and fires its triggered stop
+        /// orders, callable by anyone.
+        ///
+        /// Pending orders are processed oldest first and rest on the book once they no
+        /// longer cross; what is left of a fired stop-market order is unlocked instead.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        /// * `max_fills` - The maximum number of maker orders to match in this call, a pending
+        ///   order settled without matching any counts as one
+        ///
+        /// # Returns
+        /// * `Result<u32>` - The number of pending orders settled, Error if the pair is not listed
+        #[ink(message)]
+        pub fn crank(&mut self, pair: Pair, max_fills: u32) -> Result<u32> {
+            self.ensure_not_paused()?;
+            self.ensure_migrated()?;
+
+            let now = self.env().block_timestamp();
+            let (settled, evts) =
+                self.order_book
+                    .match_pending_orders(pair, now, max_fills, &mut self.vault)?;
+
+            self.emit_book_events(&evts);
+            self.record_last_price(pair, &evts);
+            self.fire_stops(pair)?;
+
+            Ok(settled)
+        }
+
+        /// Removes expired orders of a market and unlocks their tokens, callable by anyone.
+        ///
+        /// # Arguments
+        /// * `pair` - The trading pair (base, quote) of token contracts
+        /// * `limit` - The maximum number of orders to remove in this call
+        ///
+        /// # Returns
+        /// * `Result<u32>` - The number of removed orders, Error if the pair is not listed
+        #[ink(message)]
+        pub fn sweep_expired(&mut self, pair: Pair, limit: u32) -> Result<u32> {
+            self.ensure_migrated()?;
+
+            let now = self.env().block_timestamp();
+            let evts = self
+                .order_book
+                .remove_expired_orders(pair, now, limit, &mut self.vault)?;
+
+            self.emit_book_events(&evts);
+
+            Ok(evts.len() as u32)
+        }
     }
 }
 
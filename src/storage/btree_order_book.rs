@@ -1,16 +1,106 @@
 use ink::{
-    prelude::{collections::BTreeMap, vec::Vec},
+    prelude::{boxed::Box, collections::BTreeSet, vec::Vec},
     primitives::AccountId,
     storage::Mapping,
 };
 
+use super::queue::{Link, Queue};
 use crate::{
     error::{Error, Result},
     traits::{order_book::OrderBook, token_vault::TokenVault},
-    types::{EventFilled, Order, Side, Token},
+    types::{
+        BookEvent, Depth, EventCancelled, EventExpired, EventFilled, EventTrade, Order, Pair,
+        SelfTradePrevention, Side, Spread, TimeInForce, BPS_DENOMINATOR,
+    },
 };
 
-type StorageBTreeMap = BTreeMap<(u128, u64, u64), u64>;
+/// Index of the price levels and expiry times of a single trading pair.
+///
+/// Only prices and times live here, the orders of a level are kept in
+/// `BTreeOrderBook::levels` and `BTreeOrderBook::links`, those expiring at a time in
+/// `BTreeOrderBook::expiries`, so a call decodes levels, not the whole book.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Default)]
+struct Market {
+    // prices with resting sell orders, best (lowest) first
+    ask_prices: BTreeSet<u128>,
+
+    // prices with resting buy orders, best (highest) last
+    bid_prices: BTreeSet<u128>,
+
+    // shortcut matching condition, kept in step with the price index
+    min_sell_price: u128,
+    max_buy_price: u128,
+
+    // times at which resting good-till-time orders expire, earliest first
+    expiry_times: BTreeSet<u64>,
+}
+
+impl Market {
+    fn new() -> Self {
+        Self {
+            min_sell_price: u128::MAX,
+            max_buy_price: u128::MIN,
+            ..Default::default()
+        }
+    }
+
+    fn prices_mut(&mut self, side: Side) -> &mut BTreeSet<u128> {
+        match side {
+            Side::Buy => &mut self.bid_prices,
+            Side::Sell => &mut self.ask_prices,
+        }
+    }
+
+    // prices of a side, best level first
+    fn prices(&self, side: Side) -> Box<dyn Iterator<Item = u128> + '_> {
+        match side {
+            Side::Buy => Box::new(self.bid_prices.iter().rev().copied()),
+            Side::Sell => Box::new(self.ask_prices.iter().copied()),
+        }
+    }
+
+    fn best_price(&self, side: Side) -> Option<u128> {
+        match side {
+            Side::Buy => self.bid_prices.last().copied(),
+            Side::Sell => self.ask_prices.first().copied(),
+        }
+    }
+
+    // whether any good-till-time order has expired at `now`
+    fn has_expired(&self, now: u64) -> bool {
+        self.expiry_times
+            .first()
+            .is_some_and(|&expires_at| expires_at <= now)
+    }
+
+    // refresh the shortcut of a side after a price level was added or removed
+    fn refresh_best_price(&mut self, side: Side) {
+        match side {
+            Side::Buy => self.max_buy_price = self.best_price(Side::Buy).unwrap_or(u128::MIN),
+            Side::Sell => self.min_sell_price = self.best_price(Side::Sell).unwrap_or(u128::MAX),
+        }
+    }
+}
+
+/// Resting orders at a single price, oldest first.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Default)]
+struct Level {
+    // order ids, the head is matched first
+    queue: Queue,
+    // unfilled qty of all orders in the level
+    total_qty: u128,
+}
+
+type LevelKey = (Pair, Side, u128);
+
+type ExpiryKey = (Pair, u64);
+
+// (owner, side)
+type OwnerKey = (AccountId, Side);
 
 #[ink::storage_item]
 #[derive(Default)]
@@ -18,376 +108,1421 @@ pub struct BTreeOrderBook {
     // all orders
     orders: Mapping<u64, Order>,
 
-    // sell orders: (price, timestamp, order_id) -> order_id
-    sell_orders: StorageBTreeMap,
+    // markets: (base, quote) -> price index
+    markets: Mapping<Pair, Market>,
+
+    // price levels: (pair, side, price) -> FIFO queue of order ids
+    levels: Mapping<LevelKey, Level>,
 
-    // buy orders: (Reverse(price), timestamp, order_id) -> order_id
-    buy_orders: StorageBTreeMap,
+    // order id -> neighbours in its level
+    links: Mapping<u64, Link>,
+
+    // expiry times: (pair, expires_at) -> resting orders expiring then, oldest first
+    expiries: Mapping<ExpiryKey, Queue>,
+
+    // order id -> neighbours among the orders expiring with it
+    expiry_links: Mapping<u64, Link>,
+
+    // crossing orders stopped by their max fills per market, oldest first
+    pending: Mapping<Pair, Queue>,
+
+    // order id -> neighbours among the pending orders of its market
+    pending_links: Mapping<u64, Link>,
+
+    // open orders per owner and side, oldest first
+    owner_queues: Mapping<OwnerKey, Queue>,
+
+    // order id -> neighbours in its owner's queue
+    owner_links: Mapping<u64, Link>,
+
+    // (owner, client order id) -> order id, kept after the order is gone
+    client_orders: Mapping<(AccountId, u64), u64>,
 
     // order id generator
     next_order_id: u64,
 
-    // shortcut matching condition
-    min_sell_price: u128,
-    max_buy_price: u128,
+    // trade id generator
+    next_trade_id: u64,
+
+    // fee rates in basis points
+    maker_fee_bps: u16,
+    taker_fee_bps: u16,
 }
 
 impl BTreeOrderBook {
     pub fn new() -> Self {
-        Self {
-            min_sell_price: u128::MAX,
-            max_buy_price: u128::MIN,
-            ..Default::default()
+        Default::default()
+    }
+
+    fn get_market(&self, pair: Pair) -> Result<Market> {
+        self.markets.get(pair).ok_or(Error::PairNotSupported)
+    }
+
+    fn get_level(&self, key: LevelKey) -> Result<Level> {
+        self.levels.get(key).ok_or(Error::PriceLevelNotFound)
+    }
+
+    // append an order to the back of its price level
+    fn push_back(&mut self, market: &mut Market, order: &Order) -> Result<()> {
+        let key = (order.pair, order.side, order.price);
+        let level = match self.levels.get(key) {
+            Some(mut level) => {
+                level.total_qty = level
+                    .total_qty
+                    .checked_add(order.visible_qty())
+                    .ok_or(Error::Overflow)?;
+                level.queue.push_back(&mut self.links, order.id);
+                level
+            }
+            None => {
+                market.prices_mut(order.side).insert(order.price);
+                market.refresh_best_price(order.side);
+                Level {
+                    queue: Queue::new(&mut self.links, order.id),
+                    total_qty: order.visible_qty(),
+                }
+            }
+        };
+        self.levels.insert(key, &level);
+        Ok(())
+    }
+
+    // take an order out of its price level, dropping the level once empty
+    fn unlink(&mut self, market: &mut Market, order: &Order) -> Result<()> {
+        let key = (order.pair, order.side, order.price);
+        let mut level = self.get_level(key)?;
+        if level.queue.remove(&mut self.links, order.id) {
+            self.levels.remove(key);
+            market.prices_mut(order.side).remove(&order.price);
+            market.refresh_best_price(order.side);
+            return Ok(());
         }
+        level.total_qty = level.total_qty.saturating_sub(order.visible_qty());
+        self.levels.insert(key, &level);
+        Ok(())
     }
-}
 
-impl core::fmt::Debug for BTreeOrderBook {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("BTreeOrderBook").finish()
+    // account a partial fill of a resting order in its level
+    fn reduce_level(&mut self, order: &Order, filled_qty: u128) -> Result<()> {
+        let key = (order.pair, order.side, order.price);
+        let mut level = self.get_level(key)?;
+        level.total_qty = level.total_qty.saturating_sub(filled_qty);
+        self.levels.insert(key, &level);
+        Ok(())
     }
-}
 
-impl OrderBook for BTreeOrderBook {
-    fn make_new_order(
+    // re-queue an iceberg order whose visible slice is used up at the back of its level
+    fn requeue_slice(&mut self, market: &mut Market, order: &mut Order) -> Result<()> {
+        self.unlink(market, order)?;
+        order.show_next_slice();
+        self.push_back(market, order)?;
+        self.orders.insert(order.id, order);
+        Ok(())
+    }
+
+    // clear a resting order from its level and expiry time
+    fn remove_resting(&mut self, market: &mut Market, order: &Order) -> Result<()> {
+        self.unlink(market, order)?;
+        self.remove_expiry(market, order);
+        self.delete_order(order);
+        Ok(())
+    }
+
+    // index a resting good-till-time order by the time it expires
+    fn push_expiry(&mut self, market: &mut Market, key: ExpiryKey, order_id: u64) {
+        let (_, expires_at) = key;
+        let queue = match self.expiries.get(key) {
+            Some(mut queue) => {
+                queue.push_back(&mut self.expiry_links, order_id);
+                queue
+            }
+            None => {
+                market.expiry_times.insert(expires_at);
+                Queue::new(&mut self.expiry_links, order_id)
+            }
+        };
+        self.expiries.insert(key, &queue);
+    }
+
+    fn remove_expiry(&mut self, market: &mut Market, order: &Order) {
+        let Some(expires_at) = order.expires_at else {
+            return;
+        };
+        let key = (order.pair, expires_at);
+        let Some(mut queue) = self.expiries.get(key) else {
+            return;
+        };
+        if queue.remove(&mut self.expiry_links, order.id) {
+            self.expiries.remove(key);
+            market.expiry_times.remove(&expires_at);
+        } else {
+            self.expiries.insert(key, &queue);
+        }
+    }
+
+    // queue an order stopped by its max fills behind the other pending orders
+    fn push_pending(&mut self, pair: Pair, order_id: u64) {
+        let queue = match self.pending.get(pair) {
+            Some(mut queue) => {
+                queue.push_back(&mut self.pending_links, order_id);
+                queue
+            }
+            None => Queue::new(&mut self.pending_links, order_id),
+        };
+        self.pending.insert(pair, &queue);
+    }
+
+    // take the oldest pending order of a market
+    fn pop_pending(&mut self, pair: Pair) -> Option<u64> {
+        let queue = self.pending.get(pair)?;
+        self.remove_pending(pair, queue.head);
+        Some(queue.head)
+    }
+
+    // put a pending order back in front of the others
+    fn unpop_pending(&mut self, pair: Pair, order_id: u64) {
+        let queue = match self.pending.get(pair) {
+            Some(mut queue) => {
+                queue.push_front(&mut self.pending_links, order_id);
+                queue
+            }
+            None => Queue::new(&mut self.pending_links, order_id),
+        };
+        self.pending.insert(pair, &queue);
+    }
+
+    fn remove_pending(&mut self, pair: Pair, order_id: u64) {
+        let Some(mut queue) = self.pending.get(pair) else {
+            return;
+        };
+        if queue.remove(&mut self.pending_links, order_id) {
+            self.pending.remove(pair);
+        } else {
+            self.pending.insert(pair, &queue);
+        }
+    }
+
+    // whether an order waits for the crank rather than resting on the book
+    fn is_pending(&self, order_id: u64) -> bool {
+        self.pending_links.contains(order_id)
+    }
+
+    // keep a new order and index it by owner
+    fn store_new_order(&mut self, order: &Order) {
+        self.orders.insert(order.id, order);
+        self.push_owner_order((order.owner, order.side), order.id);
+    }
+
+    // append an order to its owner's queue, ids only grow so it stays in id order
+    fn push_owner_order(&mut self, key: OwnerKey, order_id: u64) {
+        let queue = match self.owner_queues.get(key) {
+            Some(mut queue) => {
+                queue.push_back(&mut self.owner_links, order_id);
+                queue
+            }
+            None => Queue::new(&mut self.owner_links, order_id),
+        };
+        self.owner_queues.insert(key, &queue);
+    }
+
+    // put a stored order on the book
+    fn rest_order(&mut self, market: &mut Market, order: &Order) -> Result<()> {
+        self.push_back(market, order)?;
+        if let Some(expires_at) = order.expires_at {
+            self.push_expiry(market, (order.pair, expires_at), order.id);
+        }
+        Ok(())
+    }
+
+    // drop a resting order and its owner index entry
+    fn delete_order(&mut self, order: &Order) {
+        self.orders.remove(order.id);
+        let key = (order.owner, order.side);
+        let Some(mut queue) = self.owner_queues.get(key) else {
+            return;
+        };
+        if queue.remove(&mut self.owner_links, order.id) {
+            self.owner_queues.remove(key);
+        } else {
+            self.owner_queues.insert(key, &queue);
+        }
+    }
+
+    // open order ids of an owner on one side or both, oldest first
+    fn owner_order_ids(
         &self,
         acct_id: AccountId,
-        pair: (Token, Token),
-        side: Side,
+        side: Option<Side>,
+    ) -> impl Iterator<Item = u64> + '_ {
+        let side_ids = |queue_side: Side| {
+            let head = side
+                .is_none_or(|side| side == queue_side)
+                .then(|| self.owner_queues.get((acct_id, queue_side)))
+                .flatten()
+                .map(|queue| queue.head);
+            core::iter::successors(head, |&order_id| Queue::next(&self.owner_links, order_id))
+                .peekable()
+        };
+        let (mut buys, mut sells) = (side_ids(Side::Buy), side_ids(Side::Sell));
+        // both queues are in id order, merge them
+        core::iter::from_fn(move || match (buys.peek(), sells.peek()) {
+            (Some(buy_id), Some(sell_id)) if sell_id < buy_id => sells.next(),
+            (Some(_), _) => buys.next(),
+            (None, _) => sells.next(),
+        })
+    }
+
+    // fee on an amount received by the maker or the taker, rounded down
+    fn fee(&self, amt: u128, is_maker: bool) -> u128 {
+        let bps = u128::from(if is_maker {
+            self.maker_fee_bps
+        } else {
+            self.taker_fee_bps
+        });
+        // split `amt` so no product exceeds it, bps never exceed the denominator
+        #[allow(clippy::arithmetic_side_effects)]
+        {
+            amt / BPS_DENOMINATOR * bps + amt % BPS_DENOMINATOR * bps / BPS_DENOMINATOR
+        }
+    }
+
+    // record a match of an incoming taker against a resting maker
+    fn trade(
+        &mut self,
+        maker: &Order,
+        taker: &Order,
         price: u128,
         qty: u128,
-        now: u64,
-    ) -> Order {
-        let order_id = self.next_order_id;
-        Order {
-            id: order_id,
-            pair,
-            owner: acct_id,
-            side,
+        quote_amount: u128,
+    ) -> Result<BookEvent> {
+        let trade_id = self.next_trade_id;
+        self.next_trade_id = trade_id.checked_add(1).ok_or(Error::Overflow)?;
+        Ok(BookEvent::Trade(EventTrade {
+            trade_id,
+            pair: taker.pair,
+            maker_order_id: maker.id,
+            taker_order_id: taker.id,
+            maker: maker.owner,
+            taker: taker.owner,
+            taker_side: taker.side,
             price,
             qty,
-            timestamp: now,
-            locked: 0,
+            quote_amount,
+        }))
+    }
+
+    // whether a resting order at `price` is within the taker's price
+    fn crosses(taker: &Order, price: u128) -> bool {
+        match taker.side {
+            Side::Buy => price <= taker.price,
+            Side::Sell => price >= taker.price,
         }
     }
 
-    fn insert_new_order(&mut self, order: Order) {
-        self.orders.insert(order.id, &order);
-        #[allow(clippy::arithmetic_side_effects)]
-        {
-            self.next_order_id += 1;
+    // unfilled qty of a level, leaving out expired orders
+    fn live_qty(&self, market: &Market, key: LevelKey, now: u64) -> Result<u128> {
+        let level = self.get_level(key)?;
+        if !market.has_expired(now) {
+            return Ok(level.total_qty);
+        }
+        let mut qty = 0u128;
+        let mut next = Some(level.queue.head);
+        while let Some(order_id) = next {
+            let order = self
+                .orders
+                .get(order_id)
+                .ok_or(Error::OrderNotFound(order_id))?;
+            if !order.is_expired(now) {
+                qty = qty.saturating_add(order.visible_qty());
+            }
+            next = Queue::next(&self.links, order_id);
+        }
+        Ok(qty)
+    }
+
+    // sum up live orders per price level, best level first
+    fn aggregate_levels(
+        &self,
+        pair: Pair,
+        market: &Market,
+        side: Side,
+        levels: usize,
+        now: u64,
+    ) -> Result<Vec<(u128, u128)>> {
+        let mut aggregated = Vec::new();
+        for price in market.prices(side) {
+            if aggregated.len() == levels {
+                break;
+            }
+            let qty = self.live_qty(market, (pair, side, price), now)?;
+            if qty > 0 {
+                aggregated.push((price, qty));
+            }
         }
+        Ok(aggregated)
+    }
+
+    // resolve a match between orders of the same owner, returns whether the taker goes on
+    fn prevent_self_trade<V: TokenVault>(
+        &mut self,
+        market: &mut Market,
+        taker: &mut Order,
+        mut maker: Order,
+        vault: &mut V,
+        evts: &mut Vec<BookEvent>,
+    ) -> Result<bool> {
+        let (cancel_taker, cancel_maker) = match taker.stp {
+            SelfTradePrevention::CancelNewest => (true, false),
+            SelfTradePrevention::CancelOldest => (false, true),
+            SelfTradePrevention::CancelBoth => (true, true),
+            SelfTradePrevention::Decrement => {
+                // as if traded at the maker price, without moving funds
+                let (qty, price) = (taker.qty.min(maker.visible_qty()), maker.price);
+                Self::release(taker, qty, price, vault)?;
+                Self::release(&mut maker, qty, price, vault)?;
+                self.reduce_level(&maker, qty)?;
+                if maker.visible_qty() > 0 {
+                    self.orders.insert(maker.id, &maker);
+                } else if maker.qty > 0 {
+                    self.requeue_slice(market, &mut maker)?;
+                }
+                (taker.qty == 0, maker.qty == 0)
+            }
+        };
+        if cancel_maker {
+            Self::unlock_remaining(&maker, vault)?;
+            self.remove_resting(market, &maker)?;
+            evts.push(BookEvent::Cancelled(EventCancelled::new(maker.id)));
+        }
+        if cancel_taker {
+            Self::unlock_remaining(taker, vault)?;
+            taker.qty = 0;
+            taker.locked = 0;
+            evts.push(BookEvent::Cancelled(EventCancelled::new(taker.id)));
+        }
+        Ok(!cancel_taker)
+    }
 
+    // unlock the funds of `qty` of an order, buys at `price` per unit
+    fn release<V: TokenVault>(
+        order: &mut Order,
+        qty: u128,
+        price: u128,
+        vault: &mut V,
+    ) -> Result<()> {
+        let (base, quote) = order.pair;
         match order.side {
             Side::Buy => {
-                #[allow(clippy::arithmetic_side_effects)]
-                let key = (u128::MAX - order.price, order.timestamp, order.id);
-                self.buy_orders.insert(key, order.id);
-                self.max_buy_price = self.max_buy_price.max(order.price);
+                let amt = qty.saturating_mul(price).min(order.locked);
+                vault.unlock(order.owner, quote, amt)?;
+                order.locked = order.locked.checked_sub(amt).ok_or(Error::Overflow)?;
             }
             Side::Sell => {
-                #[allow(clippy::arithmetic_side_effects)]
-                let key = (order.price, order.timestamp, order.id);
-                self.sell_orders.insert(key, order.id);
-                self.min_sell_price = self.min_sell_price.min(order.price);
+                vault.unlock(order.owner, base, qty)?;
+                order.locked = order.locked.checked_sub(qty).ok_or(Error::Overflow)?;
             }
         }
+        order.qty = order.qty.checked_sub(qty).ok_or(Error::Overflow)?;
+        Ok(())
     }
 
-    fn match_sell_orders<V: TokenVault>(
+    // buy orders keep the unfilled quote locked, sell orders the unfilled base qty
+    fn unlock_remaining<V: TokenVault>(order: &Order, vault: &mut V) -> Result<()> {
+        let (base, quote) = order.pair;
+        match order.side {
+            Side::Buy if order.locked > 0 => vault.unlock(order.owner, quote, order.locked),
+            Side::Sell => vault.unlock(order.owner, base, order.qty),
+            _ => Ok(()),
+        }
+    }
+
+    // as `match_sell_orders`, also returns how many maker orders were processed
+    fn take_sell_orders<V: TokenVault>(
         &mut self,
         mut buy_order: Order,
+        max_fills: Option<u32>,
         vault: &mut V,
-    ) -> Result<(Option<Order>, Vec<EventFilled>)> {
+    ) -> Result<(Option<Order>, Vec<BookEvent>, u32)> {
         let mut evts = Vec::new();
-        if buy_order.price < self.min_sell_price {
-            return Ok((Some(buy_order), evts));
+        let max_fills = max_fills.unwrap_or(u32::MAX);
+        let mut fills = 0u32;
+        let mut market = self.get_market(buy_order.pair)?;
+        if buy_order.price < market.min_sell_price {
+            return Ok((Some(buy_order), evts, fills));
         }
 
         let (base, quote) = buy_order.pair;
-        while let Some(entry) = self.sell_orders.first_entry() {
-            // 1. lowest sell order
-            let (sell_price, ..) = entry.key();
-            let order_id = *entry.get();
+        while let Some(sell_price) = market.best_price(Side::Sell) {
+            // 1. if can match, within the max fills
+            if sell_price > buy_order.price || fills == max_fills {
+                break;
+            }
+            // locked quote caps the qty of buys sized by quote amount
+            let affordable = buy_order
+                .locked
+                .checked_div(sell_price)
+                .ok_or(Error::ZeroPrice)?;
+            let fill_qty = buy_order.qty.min(affordable);
+            if fill_qty == 0 {
+                break;
+            }
+            // 2. oldest sell order of the lowest level
+            let order_id = self
+                .get_level((buy_order.pair, Side::Sell, sell_price))?
+                .queue
+                .head;
             let mut sell_order = self
                 .orders
                 .get(order_id)
                 .ok_or(Error::OrderNotFound(order_id))?;
-
-            // 2. if can match
-            if sell_price > &buy_order.price {
+            fills = fills.checked_add(1).ok_or(Error::Overflow)?;
+
+            // 2.1 drop expired sell_order
+            if sell_order.is_expired(buy_order.timestamp) {
+                Self::unlock_remaining(&sell_order, vault)?;
+                self.remove_resting(&mut market, &sell_order)?;
+                evts.push(BookEvent::Expired(EventExpired::new(order_id)));
+                continue;
+            }
+            // 2.2 self-trade prevention
+            if sell_order.owner == buy_order.owner {
+                if self.prevent_self_trade(
+                    &mut market,
+                    &mut buy_order,
+                    sell_order,
+                    vault,
+                    &mut evts,
+                )? {
+                    continue;
+                }
                 break;
             }
-            // 2.1 finalize sell_order
-            // assert sell_price <= buy_price
+            // 2.3 finalize sell_order
+            // assert sell_price <= buy_price, trade at the maker's price
             let deal_price = sell_order.price;
-            if sell_order.qty <= buy_order.qty {
+            let shown_qty = sell_order.visible_qty();
+            if shown_qty <= fill_qty {
                 // quote transfer
-                let quote_amt = deal_price.checked_mul(sell_order.qty).unwrap();
-                // checked
-                #[allow(clippy::arithmetic_side_effects)]
-                {
-                    buy_order.qty -= sell_order.qty;
-                    buy_order.locked -= quote_amt;
-                }
-                vault.transfer_locked(buy_order.owner, sell_order.owner, quote, quote_amt)?;
+                let quote_amt = deal_price.checked_mul(shown_qty).ok_or(Error::Overflow)?;
+                buy_order.qty = buy_order
+                    .qty
+                    .checked_sub(shown_qty)
+                    .ok_or(Error::Overflow)?;
+                buy_order.locked = buy_order
+                    .locked
+                    .checked_sub(quote_amt)
+                    .ok_or(Error::Overflow)?;
+                // fees are taken from what each side receives
+                let sell_fee = self.fee(quote_amt, true);
+                let buy_fee = self.fee(shown_qty, false);
+                vault.transfer_locked(
+                    buy_order.owner,
+                    sell_order.owner,
+                    quote,
+                    quote_amt,
+                    sell_fee,
+                )?;
 
                 // base transfer
-                vault.transfer_locked(sell_order.owner, buy_order.owner, base, sell_order.qty)?;
-
-                // clear sell order
-                entry.remove_entry();
-                self.orders.remove(order_id);
+                vault.transfer_locked(
+                    sell_order.owner,
+                    buy_order.owner,
+                    base,
+                    shown_qty,
+                    buy_fee,
+                )?;
+
+                // clear sell order, or re-queue the next slice of an iceberg
+                let sell_left = sell_order
+                    .qty
+                    .checked_sub(shown_qty)
+                    .ok_or(Error::Overflow)?;
+                if sell_left > 0 {
+                    sell_order.qty = sell_left;
+                    sell_order.locked = sell_order
+                        .locked
+                        .checked_sub(shown_qty)
+                        .ok_or(Error::Overflow)?;
+                    self.reduce_level(&sell_order, shown_qty)?;
+                    self.requeue_slice(&mut market, &mut sell_order)?;
+                } else {
+                    self.remove_resting(&mut market, &sell_order)?;
+                }
 
                 // emit
-                evts.push(EventFilled::new(sell_order.id, deal_price, sell_order.qty));
-                evts.push(EventFilled::new(buy_order.id, deal_price, sell_order.qty));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    sell_order.id,
+                    deal_price,
+                    shown_qty,
+                    sell_left,
+                    sell_fee,
+                )));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    buy_order.id,
+                    deal_price,
+                    shown_qty,
+                    buy_order.qty,
+                    buy_fee,
+                )));
+                evts.push(self.trade(&sell_order, &buy_order, deal_price, shown_qty, quote_amt)?);
             }
-            // 2.2 partial fill
+            // 2.4 partial fill
             else {
                 // quote transfer
-                let quote_amt = deal_price.checked_mul(buy_order.qty).unwrap();
-                // checked
-                #[allow(clippy::arithmetic_side_effects)]
-                {
-                    buy_order.locked -= quote_amt;
-                    sell_order.qty -= buy_order.qty;
-                }
-                vault.transfer_locked(buy_order.owner, sell_order.owner, quote, quote_amt)?;
+                let quote_amt = deal_price.checked_mul(fill_qty).ok_or(Error::Overflow)?;
+                buy_order.qty = buy_order.qty.checked_sub(fill_qty).ok_or(Error::Overflow)?;
+                buy_order.locked = buy_order
+                    .locked
+                    .checked_sub(quote_amt)
+                    .ok_or(Error::Overflow)?;
+                sell_order.qty = sell_order
+                    .qty
+                    .checked_sub(fill_qty)
+                    .ok_or(Error::Overflow)?;
+                sell_order.locked = sell_order
+                    .locked
+                    .checked_sub(fill_qty)
+                    .ok_or(Error::Overflow)?;
+                // fees are taken from what each side receives
+                let sell_fee = self.fee(quote_amt, true);
+                let buy_fee = self.fee(fill_qty, false);
+                vault.transfer_locked(
+                    buy_order.owner,
+                    sell_order.owner,
+                    quote,
+                    quote_amt,
+                    sell_fee,
+                )?;
 
                 // base transfer
-                vault.transfer_locked(sell_order.owner, buy_order.owner, base, buy_order.qty)?;
+                vault.transfer_locked(
+                    sell_order.owner,
+                    buy_order.owner,
+                    base,
+                    fill_qty,
+                    buy_fee,
+                )?;
 
                 // update sell order
                 self.orders.insert(order_id, &sell_order);
+                self.reduce_level(&sell_order, fill_qty)?;
 
                 // emit
-                evts.push(EventFilled::new(sell_order.id, deal_price, buy_order.qty));
-                evts.push(EventFilled::new(buy_order.id, deal_price, buy_order.qty));
-
-                buy_order.qty = 0;
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    sell_order.id,
+                    deal_price,
+                    fill_qty,
+                    sell_order.qty,
+                    sell_fee,
+                )));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    buy_order.id,
+                    deal_price,
+                    fill_qty,
+                    buy_order.qty,
+                    buy_fee,
+                )));
+                evts.push(self.trade(&sell_order, &buy_order, deal_price, fill_qty, quote_amt)?);
                 break;
             }
         }
 
+        self.markets.insert(buy_order.pair, &market);
+
         if buy_order.qty > 0 {
-            Ok((Some(buy_order), evts))
+            // keep only what the remainder needs at its price, fills below it left a surplus
+            // (buys sized by quote amount have no such bound)
+            if let Some(required) = buy_order.price.checked_mul(buy_order.qty) {
+                if let Some(surplus) = buy_order
+                    .locked
+                    .checked_sub(required)
+                    .filter(|&surplus| surplus > 0)
+                {
+                    vault.unlock(buy_order.owner, quote, surplus)?;
+                    buy_order.locked = required;
+                }
+            }
+            Ok((Some(buy_order), evts, fills))
         } else {
             // unlock remaining
             if buy_order.locked > 0 {
                 vault.unlock(buy_order.owner, quote, buy_order.locked)?;
             }
-            Ok((None, evts))
+            Ok((None, evts, fills))
         }
     }
 
-    fn match_buy_orders<V: TokenVault>(
+    // as `match_buy_orders`, also returns how many maker orders were processed
+    fn take_buy_orders<V: TokenVault>(
         &mut self,
         mut sell_order: Order,
+        max_fills: Option<u32>,
         vault: &mut V,
-    ) -> Result<(Option<Order>, Vec<EventFilled>)> {
+    ) -> Result<(Option<Order>, Vec<BookEvent>, u32)> {
         let mut evts = Vec::new();
-        if sell_order.price > self.max_buy_price {
-            return Ok((Some(sell_order), evts));
+        let max_fills = max_fills.unwrap_or(u32::MAX);
+        let mut fills = 0u32;
+        let mut market = self.get_market(sell_order.pair)?;
+        if sell_order.price > market.max_buy_price {
+            return Ok((Some(sell_order), evts, fills));
         }
 
         let (base, quote) = sell_order.pair;
-        while let Some(entry) = self.buy_orders.first_entry() {
-            // 1. highest buy order
-            let (buy_price, ..) = entry.key();
-            #[allow(clippy::arithmetic_side_effects)]
-            let buy_price = &(u128::MAX - buy_price);
-            let order_id = *entry.get();
+        while let Some(buy_price) = market.best_price(Side::Buy) {
+            // 1. if can match, within the max fills
+            if buy_price < sell_order.price || fills == max_fills || sell_order.qty == 0 {
+                break;
+            }
+            // 2. oldest buy order of the highest level
+            let order_id = self
+                .get_level((sell_order.pair, Side::Buy, buy_price))?
+                .queue
+                .head;
             let mut buy_order = self
                 .orders
                 .get(order_id)
                 .ok_or(Error::OrderNotFound(order_id))?;
-
-            // 2. if can match
-            if buy_price < &sell_order.price {
+            fills = fills.checked_add(1).ok_or(Error::Overflow)?;
+
+            // 2.1 drop expired buy_order
+            if buy_order.is_expired(sell_order.timestamp) {
+                Self::unlock_remaining(&buy_order, vault)?;
+                self.remove_resting(&mut market, &buy_order)?;
+                evts.push(BookEvent::Expired(EventExpired::new(order_id)));
+                continue;
+            }
+            // 2.2 self-trade prevention
+            if buy_order.owner == sell_order.owner {
+                if self.prevent_self_trade(
+                    &mut market,
+                    &mut sell_order,
+                    buy_order,
+                    vault,
+                    &mut evts,
+                )? {
+                    continue;
+                }
                 break;
             }
-            // 2.1 finalize buy_order
-            // assert sell_price <= buy_price
-            let deal_price = sell_order.price;
-            if buy_order.qty <= sell_order.qty {
+            // 2.3 finalize buy_order
+            // assert sell_price <= buy_price, trade at the maker's price
+            let deal_price = buy_order.price;
+            let shown_qty = buy_order.visible_qty();
+            if shown_qty <= sell_order.qty {
                 // quote transfer
-                let quote_amt = deal_price.checked_mul(buy_order.qty).unwrap();
-                // checked
-                #[allow(clippy::arithmetic_side_effects)]
-                {
-                    sell_order.qty -= buy_order.qty;
-                    buy_order.locked -= quote_amt;
-                }
-                vault.transfer_locked(buy_order.owner, sell_order.owner, quote, quote_amt)?;
+                let quote_amt = deal_price.checked_mul(shown_qty).ok_or(Error::Overflow)?;
+                sell_order.qty = sell_order
+                    .qty
+                    .checked_sub(shown_qty)
+                    .ok_or(Error::Overflow)?;
+                sell_order.locked = sell_order
+                    .locked
+                    .checked_sub(shown_qty)
+                    .ok_or(Error::Overflow)?;
+                buy_order.locked = buy_order
+                    .locked
+                    .checked_sub(quote_amt)
+                    .ok_or(Error::Overflow)?;
+                // fees are taken from what each side receives
+                let buy_fee = self.fee(shown_qty, true);
+                let sell_fee = self.fee(quote_amt, false);
+                vault.transfer_locked(
+                    buy_order.owner,
+                    sell_order.owner,
+                    quote,
+                    quote_amt,
+                    sell_fee,
+                )?;
 
                 // base transfer
-                vault.transfer_locked(sell_order.owner, buy_order.owner, base, buy_order.qty)?;
-
-                // unlock remaining when complete
-                if buy_order.locked > 0 {
-                    vault.unlock(buy_order.owner, quote, buy_order.locked)?;
+                vault.transfer_locked(
+                    sell_order.owner,
+                    buy_order.owner,
+                    base,
+                    shown_qty,
+                    buy_fee,
+                )?;
+
+                // clear buy order, or re-queue the next slice of an iceberg
+                let buy_left = buy_order
+                    .qty
+                    .checked_sub(shown_qty)
+                    .ok_or(Error::Overflow)?;
+                if buy_left > 0 {
+                    buy_order.qty = buy_left;
+                    self.reduce_level(&buy_order, shown_qty)?;
+                    self.requeue_slice(&mut market, &mut buy_order)?;
+                } else {
+                    // unlock remaining when complete
+                    if buy_order.locked > 0 {
+                        vault.unlock(buy_order.owner, quote, buy_order.locked)?;
+                    }
+                    self.remove_resting(&mut market, &buy_order)?;
                 }
-                // clear buy order
-                entry.remove_entry();
-                self.orders.remove(order_id);
 
                 // emit
-                evts.push(EventFilled::new(buy_order.id, deal_price, buy_order.qty));
-                evts.push(EventFilled::new(sell_order.id, deal_price, buy_order.qty));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    buy_order.id,
+                    deal_price,
+                    shown_qty,
+                    buy_left,
+                    buy_fee,
+                )));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    sell_order.id,
+                    deal_price,
+                    shown_qty,
+                    sell_order.qty,
+                    sell_fee,
+                )));
+                evts.push(self.trade(&buy_order, &sell_order, deal_price, shown_qty, quote_amt)?);
             }
-            // 2.2 partial fill
+            // 2.4 partial fill
             else {
                 // quote transfer
-                let quote_amt = deal_price.checked_mul(sell_order.qty).unwrap();
-                // checked
-                #[allow(clippy::arithmetic_side_effects)]
-                {
-                    buy_order.locked -= quote_amt;
-                    buy_order.qty -= sell_order.qty;
-                }
-                vault.transfer_locked(buy_order.owner, sell_order.owner, quote, quote_amt)?;
+                let quote_amt = deal_price
+                    .checked_mul(sell_order.qty)
+                    .ok_or(Error::Overflow)?;
+                buy_order.locked = buy_order
+                    .locked
+                    .checked_sub(quote_amt)
+                    .ok_or(Error::Overflow)?;
+                buy_order.qty = buy_order
+                    .qty
+                    .checked_sub(sell_order.qty)
+                    .ok_or(Error::Overflow)?;
+                // fees are taken from what each side receives
+                let buy_fee = self.fee(sell_order.qty, true);
+                let sell_fee = self.fee(quote_amt, false);
+                vault.transfer_locked(
+                    buy_order.owner,
+                    sell_order.owner,
+                    quote,
+                    quote_amt,
+                    sell_fee,
+                )?;
 
                 // base transfer
-                vault.transfer_locked(sell_order.owner, buy_order.owner, base, sell_order.qty)?;
+                vault.transfer_locked(
+                    sell_order.owner,
+                    buy_order.owner,
+                    base,
+                    sell_order.qty,
+                    buy_fee,
+                )?;
                 // update buy order
                 self.orders.insert(order_id, &buy_order);
+                self.reduce_level(&buy_order, sell_order.qty)?;
 
                 // emit
-                evts.push(EventFilled::new(buy_order.id, deal_price, sell_order.qty));
-                evts.push(EventFilled::new(sell_order.id, deal_price, sell_order.qty));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    buy_order.id,
+                    deal_price,
+                    sell_order.qty,
+                    buy_order.qty,
+                    buy_fee,
+                )));
+                evts.push(BookEvent::Filled(EventFilled::new(
+                    sell_order.id,
+                    deal_price,
+                    sell_order.qty,
+                    0,
+                    sell_fee,
+                )));
+                evts.push(self.trade(
+                    &buy_order,
+                    &sell_order,
+                    deal_price,
+                    sell_order.qty,
+                    quote_amt,
+                )?);
                 sell_order.qty = 0;
+                sell_order.locked = 0;
                 break;
             }
         }
 
+        self.markets.insert(sell_order.pair, &market);
+
         if sell_order.qty > 0 {
-            Ok((Some(sell_order), evts))
+            Ok((Some(sell_order), evts, fills))
         } else {
-            Ok((None, evts))
+            Ok((None, evts, fills))
         }
     }
+}
 
-    fn cancel_order<V: TokenVault>(
-        &mut self,
-        acct_id: AccountId,
-        order_id: u64,
-        vault: &mut V,
-    ) -> Result<()> {
-        let order = self
-            .orders
-            .get(order_id)
-            .ok_or(Error::OrderNotFound(order_id))?;
-        if order.owner != acct_id {
-            return Err(Error::Unauthorized("Only order owner can cancel".into()));
-        }
+impl core::fmt::Debug for BTreeOrderBook {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BTreeOrderBook").finish()
+    }
+}
 
-        let (base, quote) = order.pair;
-        match order.side {
-            Side::Buy => {
-                // unlock unfills
-                // assert ok: unlock always success
-                if order.locked > 0 {
-                    vault.unlock(order.owner, quote, order.locked).unwrap();
-                }
-                // clear buy order
-                #[allow(clippy::arithmetic_side_effects)]
-                let key = (u128::MAX - order.price, order.timestamp, order.id);
-                self.buy_orders.remove(&key);
-                if order.price == self.max_buy_price {
-                    self.max_buy_price = self
-                        .buy_orders
-                        .first_entry()
-                        .map(|e| u128::MAX.checked_sub(e.key().0).unwrap())
-                        .unwrap_or(0);
-                }
-            }
-            Side::Sell => {
-                // unlock unfills
-                // assert ok: unlock always success
-                vault.unlock(order.owner, base, order.qty).unwrap();
-                // clear sell order
-                #[allow(clippy::arithmetic_side_effects)]
-                let key = (order.price, order.timestamp, order.id);
-                self.sell_orders.remove(&key);
-                if order.price == self.min_sell_price {
-                    self.min_sell_price = self
-                        .sell_orders
-                        .first_entry()
-                        .map(|e| e.key().0)
-                        .unwrap_or(u128::MAX);
-                }
-            }
+impl OrderBook for BTreeOrderBook {
+    fn add_market(&mut self, pair: Pair) -> Result<()> {
+        if pair.0 == pair.1 {
+            return Err(Error::IdenticalTokens);
         }
-        self.orders.remove(order_id);
+        if self.markets.contains(pair) {
+            return Err(Error::MarketExists);
+        }
+        self.markets.insert(pair, &Market::new());
         Ok(())
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::storage::vault::Vault;
-    use ink::env::test;
-
-    fn setup() -> (BTreeOrderBook, Vault, AccountId, AccountId) {
-        let book = BTreeOrderBook::new();
-        let mut vault = Vault::default();
-        let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
-        let alice = accounts.alice;
-        let bob = accounts.bob;
-
-        // make ink engine happy
-        test::set_callee::<ink::env::DefaultEnvironment>(accounts.charlie);
-
-        // Setup initial balances
-        vault.deposit(alice, Token::Base, 1000);
-        vault.deposit(alice, Token::Quote, 1000);
-        vault.deposit(bob, Token::Base, 1000);
-        vault.deposit(bob, Token::Quote, 1000);
 
-        (book, vault, alice, bob)
+    fn has_market(&self, pair: Pair) -> bool {
+        self.markets.contains(pair)
     }
 
-    #[test]
-    fn test_basic_matching() {
-        let (mut book, mut vault, alice, bob) = setup();
-        let now = 1;
+    fn set_fee_rates(&mut self, maker_fee_bps: u16, taker_fee_bps: u16) -> Result<()> {
+        if u128::from(maker_fee_bps.max(taker_fee_bps)) > BPS_DENOMINATOR {
+            return Err(Error::FeeRateTooHigh);
+        }
+        self.maker_fee_bps = maker_fee_bps;
+        self.taker_fee_bps = taker_fee_bps;
+        Ok(())
+    }
 
-        // Alice places a buy order: 100 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 100, now);
-        vault.lock(alice, Token::Quote, 1000).unwrap(); // Lock 1000 TokenB
-        buy_order.locked = 1000;
-        book.insert_new_order(buy_order.clone());
+    fn fee_rates(&self) -> (u16, u16) {
+        (self.maker_fee_bps, self.taker_fee_bps)
+    }
 
-        // Bob places a sell order: 100 TokenA at price 10 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            100,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 100).unwrap(); // Lock 100 TokenA
+    fn make_new_order(
+        &mut self,
+        acct_id: AccountId,
+        pair: Pair,
+        side: Side,
+        price: u128,
+        qty: u128,
+        now: u64,
+    ) -> Result<Order> {
+        let order_id = self.next_order_id;
+        self.next_order_id = order_id.checked_add(1).ok_or(Error::Overflow)?;
+        Ok(Order {
+            id: order_id,
+            pair,
+            owner: acct_id,
+            side,
+            price,
+            qty,
+            timestamp: now,
+            locked: 0,
+            expires_at: None,
+            time_in_force: Default::default(),
+            max_fills: None,
+            stp: Default::default(),
+            client_order_id: None,
+            display_qty: None,
+            hidden_qty: 0,
+        })
+    }
+
+    fn insert_new_order(&mut self, mut order: Order) -> Result<()> {
+        let mut market = self.get_market(order.pair)?;
+        order.show_first_slice();
+        self.rest_order(&mut market, &order)?;
+        self.store_new_order(&order);
+        self.markets.insert(order.pair, &market);
+        Ok(())
+    }
+
+    fn park_order(&mut self, order: Order) -> Result<()> {
+        if !self.markets.contains(order.pair) {
+            return Err(Error::PairNotSupported);
+        }
+        self.store_new_order(&order);
+        self.push_pending(order.pair, order.id);
+        Ok(())
+    }
+
+    fn reserve_client_order_id(
+        &mut self,
+        acct_id: AccountId,
+        client_order_id: u64,
+        order_id: u64,
+    ) -> Result<()> {
+        let key = (acct_id, client_order_id);
+        if self.client_orders.contains(key) {
+            return Err(Error::ClientOrderIdUsed);
+        }
+        self.client_orders.insert(key, &order_id);
+        Ok(())
+    }
+
+    fn match_pending_orders<V: TokenVault>(
+        &mut self,
+        pair: Pair,
+        now: u64,
+        max_fills: u32,
+        vault: &mut V,
+    ) -> Result<(u32, Vec<BookEvent>)> {
+        if !self.markets.contains(pair) {
+            return Err(Error::PairNotSupported);
+        }
+        let mut evts = Vec::new();
+        let mut settled = 0u32;
+        let mut budget = max_fills;
+        while budget > 0 {
+            let Some(order_id) = self.pop_pending(pair) else {
+                break;
+            };
+            let mut order = self
+                .orders
+                .get(order_id)
+                .ok_or(Error::OrderNotFound(order_id))?;
+
+            if order.is_expired(now) {
+                Self::unlock_remaining(&order, vault)?;
+                self.delete_order(&order);
+                evts.push(BookEvent::Expired(EventExpired::new(order_id)));
+                budget = budget.checked_sub(1).ok_or(Error::Overflow)?;
+                settled = settled.checked_add(1).ok_or(Error::Overflow)?;
+                continue;
+            }
+
+            // continue matching as a taker arriving now
+            order.timestamp = now;
+            let (res, order_evts, consumed) = match order.side {
+                Side::Buy => self.take_sell_orders(order.clone(), Some(budget), vault)?,
+                Side::Sell => self.take_buy_orders(order.clone(), Some(budget), vault)?,
+            };
+            // stopped by the budget rather than by the book
+            let capped = consumed == budget;
+            // an order that no longer crosses still costs a unit to settle
+            budget = budget.saturating_sub(consumed.max(1));
+            evts.extend(order_evts);
+
+            let Some(mut order) = res else {
+                self.delete_order(&order);
+                settled = settled.checked_add(1).ok_or(Error::Overflow)?;
+                continue;
+            };
+            order.show_first_slice();
+            self.orders.insert(order_id, &order);
+            if capped && self.would_cross(&order)? {
+                // out of fills, keep its place at the front
+                self.unpop_pending(pair, order_id);
+                break;
+            }
+            if order.time_in_force == TimeInForce::ImmediateOrCancel {
+                // never rests, e.g. a fired stop-market order
+                Self::unlock_remaining(&order, vault)?;
+                self.delete_order(&order);
+                evts.push(BookEvent::Cancelled(EventCancelled::new(order_id)));
+            } else {
+                let mut market = self.get_market(pair)?;
+                self.rest_order(&mut market, &order)?;
+                self.markets.insert(pair, &market);
+            }
+            settled = settled.checked_add(1).ok_or(Error::Overflow)?;
+        }
+        Ok((settled, evts))
+    }
+
+    fn match_sell_orders<V: TokenVault>(
+        &mut self,
+        buy_order: Order,
+        max_fills: Option<u32>,
+        vault: &mut V,
+    ) -> Result<(Option<Order>, Vec<BookEvent>)> {
+        let (res, evts, _) = self.take_sell_orders(buy_order, max_fills, vault)?;
+        Ok((res, evts))
+    }
+
+    fn match_buy_orders<V: TokenVault>(
+        &mut self,
+        sell_order: Order,
+        max_fills: Option<u32>,
+        vault: &mut V,
+    ) -> Result<(Option<Order>, Vec<BookEvent>)> {
+        let (res, evts, _) = self.take_buy_orders(sell_order, max_fills, vault)?;
+        Ok((res, evts))
+    }
+
+    fn would_cross(&self, order: &Order) -> Result<bool> {
+        let market = self.get_market(order.pair)?;
+        let side = order.side.opposite();
+        for price in market.prices(side) {
+            if !Self::crosses(order, price) {
+                break;
+            }
+            if self.live_qty(&market, (order.pair, side, price), order.timestamp)? > 0 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn fillable_qty(&self, order: &Order) -> Result<u128> {
+        let market = self.get_market(order.pair)?;
+        let side = order.side.opposite();
+        let mut fillable = 0u128;
+        'levels: for price in market.prices(side) {
+            if !Self::crosses(order, price) {
+                break;
+            }
+            // hidden iceberg slices re-queue at the back of their level and fill there
+            let mut hidden = 0u128;
+            let mut next = Some(self.get_level((order.pair, side, price))?.queue.head);
+            while let Some(order_id) = next {
+                if fillable >= order.qty {
+                    break 'levels;
+                }
+                let maker = self
+                    .orders
+                    .get(order_id)
+                    .ok_or(Error::OrderNotFound(order_id))?;
+                next = Queue::next(&self.links, order_id);
+                if maker.is_expired(order.timestamp) {
+                    continue;
+                }
+                // own orders never fill: they are cancelled under CancelOldest,
+                // any other mode cuts the order short at them
+                if maker.owner == order.owner {
+                    if order.stp == SelfTradePrevention::CancelOldest {
+                        continue;
+                    }
+                    break 'levels;
+                }
+                fillable = fillable.saturating_add(maker.visible_qty());
+                hidden = hidden.saturating_add(maker.hidden_qty);
+            }
+            fillable = fillable.saturating_add(hidden);
+        }
+        Ok(fillable.min(order.qty))
+    }
+
+    fn discard_order<V: TokenVault>(&mut self, order: Order, vault: &mut V) -> Result<()> {
+        Self::unlock_remaining(&order, vault)
+    }
+
+    fn cancel_order<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        order_id: u64,
+        vault: &mut V,
+    ) -> Result<()> {
+        let order = self
+            .orders
+            .get(order_id)
+            .ok_or(Error::OrderNotFound(order_id))?;
+        if order.owner != acct_id {
+            return Err(Error::NotOrderOwner);
+        }
+        let mut market = self.get_market(order.pair)?;
+
+        // unlock unfills
+        Self::unlock_remaining(&order, vault)?;
+        if self.is_pending(order_id) {
+            self.remove_pending(order.pair, order_id);
+            self.delete_order(&order);
+        } else {
+            self.remove_resting(&mut market, &order)?;
+        }
+        self.markets.insert(order.pair, &market);
+        Ok(())
+    }
+
+    fn cancel_all_orders<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        side: Option<Side>,
+        limit: u32,
+        vault: &mut V,
+    ) -> Result<Vec<u64>> {
+        // only the orders of `side` are visited
+        let cancelled: Vec<u64> = self
+            .owner_order_ids(acct_id, side)
+            .take(limit as usize)
+            .collect();
+        for &order_id in &cancelled {
+            self.cancel_order(acct_id, order_id, vault)?;
+        }
+        Ok(cancelled)
+    }
+
+    fn modify_order<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        order_id: u64,
+        price: u128,
+        qty: u128,
+        now: u64,
+        vault: &mut V,
+    ) -> Result<(Option<Order>, Vec<BookEvent>)> {
+        let mut order = self
+            .orders
+            .get(order_id)
+            .ok_or(Error::OrderNotFound(order_id))?;
+        if order.owner != acct_id {
+            return Err(Error::NotOrderOwner);
+        }
+        // waiting for a sweep, it must not rest again
+        if order.is_expired(now) {
+            return Err(Error::ExpiryInPast);
+        }
+        let mut market = self.get_market(order.pair)?;
+        if self.is_pending(order_id) {
+            return Err(Error::PendingOrderNotModifiable);
+        }
+
+        // 1. smaller qty at the same price keeps its place
+        if price == order.price && qty <= order.qty {
+            let (reduced, shown) = (
+                order.qty.checked_sub(qty).ok_or(Error::Overflow)?,
+                order.visible_qty(),
+            );
+            Self::release(&mut order, reduced, price, vault)?;
+            // the hidden part of an iceberg goes first
+            order.hidden_qty = order.hidden_qty.saturating_sub(reduced);
+            let unshown = shown
+                .checked_sub(order.visible_qty())
+                .ok_or(Error::Overflow)?;
+            self.reduce_level(&order, unshown)?;
+            self.orders.insert(order_id, &order);
+            return Ok((Some(order), Vec::new()));
+        }
+
+        // 2. a post-only order must not take at its new price either
+        if order.time_in_force == TimeInForce::PostOnly {
+            let amended = Order {
+                price,
+                timestamp: now,
+                ..order.clone()
+            };
+            if self.would_cross(&amended)? {
+                return Err(Error::PostOnlyWouldCross);
+            }
+        }
+
+        let (base, quote) = order.pair;
+        let (token, held, required) = match order.side {
+            Side::Buy => (
+                quote,
+                order.locked,
+                price.checked_mul(qty).ok_or(Error::Overflow)?,
+            ),
+            Side::Sell => (base, order.locked, qty),
+        };
+
+        // 3. otherwise leave the book
+        self.unlink(&mut market, &order)?;
+        self.remove_expiry(&mut market, &order);
+        self.markets.insert(order.pair, &market);
+
+        // 4. lock or unlock the difference
+        if required > held {
+            let more = required.checked_sub(held).ok_or(Error::Overflow)?;
+            vault.lock(acct_id, token, more)?;
+        } else {
+            let less = held.checked_sub(required).ok_or(Error::Overflow)?;
+            vault.unlock(acct_id, token, less)?;
+        }
+        order.price = price;
+        order.qty = qty;
+        order.locked = required;
+        order.timestamp = now;
+
+        // 5. match at the new price within the order's max fills and queue up what is left
+        let (res, evts) = match order.side {
+            Side::Buy => self.match_sell_orders(order.clone(), order.max_fills, vault)?,
+            Side::Sell => self.match_buy_orders(order.clone(), order.max_fills, vault)?,
+        };
+        let res = match res {
+            Some(mut remaining) => {
+                remaining.show_first_slice();
+                self.orders.insert(order_id, &remaining);
+                if remaining.max_fills.is_some() && self.would_cross(&remaining)? {
+                    // stopped by its max fills, the crank matches the rest
+                    self.push_pending(remaining.pair, order_id);
+                } else {
+                    let mut market = self.get_market(order.pair)?;
+                    self.rest_order(&mut market, &remaining)?;
+                    self.markets.insert(order.pair, &market);
+                }
+                Some(remaining)
+            }
+            None => {
+                self.delete_order(&order);
+                None
+            }
+        };
+        Ok((res, evts))
+    }
+
+    fn remove_expired_orders<V: TokenVault>(
+        &mut self,
+        pair: Pair,
+        now: u64,
+        limit: u32,
+        vault: &mut V,
+    ) -> Result<Vec<BookEvent>> {
+        let mut evts = Vec::new();
+        let mut market = self.get_market(pair)?;
+        while evts.len() < limit as usize {
+            // earliest expiry time first, oldest order first within it
+            let Some(&expires_at) = market.expiry_times.first() else {
+                break;
+            };
+            if expires_at > now {
+                break;
+            }
+            let Some(queue) = self.expiries.get((pair, expires_at)) else {
+                // kept in step with the time index, drop a time without orders
+                market.expiry_times.remove(&expires_at);
+                continue;
+            };
+            let order_id = queue.head;
+            let order = self
+                .orders
+                .get(order_id)
+                .ok_or(Error::OrderNotFound(order_id))?;
+
+            Self::unlock_remaining(&order, vault)?;
+            self.remove_resting(&mut market, &order)?;
+            evts.push(BookEvent::Expired(EventExpired::new(order_id)));
+        }
+        self.markets.insert(pair, &market);
+        Ok(evts)
+    }
+
+    fn get_order(&self, order_id: u64) -> Option<Order> {
+        self.orders.get(order_id)
+    }
+
+    fn order_id_of_client(&self, acct_id: AccountId, client_order_id: u64) -> Option<u64> {
+        self.client_orders.get((acct_id, client_order_id))
+    }
+
+    fn open_orders_of(&self, acct_id: AccountId, offset: u32, limit: u32) -> Vec<Order> {
+        self.owner_order_ids(acct_id, None)
+            .skip(offset as usize)
+            .take(limit as usize)
+            .filter_map(|order_id| self.orders.get(order_id))
+            .collect()
+    }
+
+    fn spread(&self, pair: Pair) -> Result<Spread> {
+        let market = self.get_market(pair)?;
+        let best_bid = Some(market.max_buy_price).filter(|&price| price != u128::MIN);
+        let best_ask = Some(market.min_sell_price).filter(|&price| price != u128::MAX);
+        Ok(Spread {
+            best_bid,
+            best_ask,
+            spread: best_ask
+                .zip(best_bid)
+                .map(|(ask, bid)| ask.saturating_sub(bid)),
+        })
+    }
+
+    fn depth(&self, pair: Pair, levels: u32, now: u64) -> Result<Depth> {
+        let market = self.get_market(pair)?;
+        // at least one level for best bid/ask
+        let scan = (levels as usize).max(1);
+        let mut bids = self.aggregate_levels(pair, &market, Side::Buy, scan, now)?;
+        let mut asks = self.aggregate_levels(pair, &market, Side::Sell, scan, now)?;
+        let best_bid = bids.first().map(|(price, _)| *price);
+        let best_ask = asks.first().map(|(price, _)| *price);
+        bids.truncate(levels as usize);
+        asks.truncate(levels as usize);
+        Ok(Depth {
+            best_bid,
+            best_ask,
+            bids,
+            asks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::Rng;
+    use crate::storage::vault::Vault;
+    use crate::types::Token;
+    use ink::env::test;
+
+    const BASE: Token = AccountId([0xb; 32]);
+    const QUOTE: Token = AccountId([0xc; 32]);
+    const PAIR: Pair = (BASE, QUOTE);
+
+    fn fills(events: Vec<BookEvent>) -> Vec<EventFilled> {
+        events
+            .into_iter()
+            .filter_map(|e| match e {
+                BookEvent::Filled(e) => Some(e),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn cancels(events: &[BookEvent]) -> Vec<u64> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                BookEvent::Cancelled(e) => Some(e.order_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn setup() -> (BTreeOrderBook, Vault, AccountId, AccountId) {
+        let mut book = BTreeOrderBook::new();
+        let mut vault = Vault::default();
+        let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+        let alice = accounts.alice;
+        let bob = accounts.bob;
+
+        // make ink engine happy
+        test::set_callee::<ink::env::DefaultEnvironment>(accounts.charlie);
+
+        book.add_market(PAIR).unwrap();
+
+        // Setup initial balances
+        vault.deposit(alice, BASE, 1000).unwrap();
+        vault.deposit(alice, QUOTE, 1000).unwrap();
+        vault.deposit(bob, BASE, 1000).unwrap();
+        vault.deposit(bob, QUOTE, 1000).unwrap();
+
+        (book, vault, alice, bob)
+    }
+
+    #[test]
+    fn test_basic_matching() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice places a buy order: 100 TokenA at price 10 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap(); // Lock 1000 TokenB
+        buy_order.locked = 1000;
+        book.insert_new_order(buy_order.clone()).unwrap();
+
+        // Bob places a sell order: 100 TokenA at price 10 TokenB
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap(); // Lock 100 TokenA
         sell_order.locked = 100;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 1000);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900);
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 1000);
+        assert_eq!(vault.get_balance(bob, BASE), 900);
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the orders
         let (remaining_sell, events) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
+        let events = fills(events);
         assert!(remaining_sell.is_none()); // Sell order should be fully filled
         assert_eq!(events.len(), 2); // Two fill events
 
@@ -401,14 +1536,14 @@ mod tests {
         assert_eq!(sell_event.filled_qty, 100);
 
         // Check final balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1100); // Received 100 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0); // Spent 1000 TokenB
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900); // Spent 100 TokenA
-        assert_eq!(vault.get_locked(bob, Token::Base), 0);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 2000); // Received 1000 TokenB
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1100); // Received 100 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0); // Spent 1000 TokenB
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(bob, BASE), 900); // Spent 100 TokenA
+        assert_eq!(vault.get_locked(bob, BASE), 0);
+        assert_eq!(vault.get_balance(bob, QUOTE), 2000); // Received 1000 TokenB
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
@@ -417,39 +1552,36 @@ mod tests {
         let now = 1;
 
         // Alice places a buy order: 50 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 50, now);
-        vault.lock(alice, Token::Quote, 500).unwrap(); // Lock 500 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 50, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 500).unwrap(); // Lock 500 TokenB
         buy_order.locked = 500;
-        book.insert_new_order(buy_order.clone());
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob places a sell order: 100 TokenA at price 10 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            100,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 100).unwrap(); // Lock 100 TokenA
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap(); // Lock 100 TokenA
         sell_order.locked = 100;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 500);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 500);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900); // 1000 - 100 locked
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 500);
+        assert_eq!(vault.get_locked(alice, QUOTE), 500);
+        assert_eq!(vault.get_balance(bob, BASE), 900); // 1000 - 100 locked
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the orders
         let (remaining_sell, events) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
+        let events = fills(events);
         assert!(remaining_sell.is_some()); // Sell order should be partially filled
         assert_eq!(events.len(), 2); // Two fill events
 
@@ -466,14 +1598,14 @@ mod tests {
         assert_eq!(sell_event.filled_qty, 50);
 
         // Check final balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1050); // Received 50 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 500); // Spent 500 TokenB
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900); // Still 900 because 100 was locked initially, 50 transferred, 50 still locked
-        assert_eq!(vault.get_locked(bob, Token::Base), 50); // 50 TokenA still locked
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1500); // Received 500 TokenB
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1050); // Received 50 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 500); // Spent 500 TokenB
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(bob, BASE), 900); // Still 900 because 100 was locked initially, 50 transferred, 50 still locked
+        assert_eq!(vault.get_locked(bob, BASE), 50); // 50 TokenA still locked
+        assert_eq!(vault.get_balance(bob, QUOTE), 1500); // Received 500 TokenB
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
@@ -482,39 +1614,36 @@ mod tests {
         let now = 1;
 
         // Alice places a buy order: 100 TokenA at price 8 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 8, 100, now);
-        vault.lock(alice, Token::Quote, 800).unwrap(); // Lock 800 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 8, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 800).unwrap(); // Lock 800 TokenB
         buy_order.locked = 800;
-        book.insert_new_order(buy_order.clone());
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob places a sell order: 100 TokenA at price 10 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            100,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 100).unwrap(); // Lock 100 TokenA
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap(); // Lock 100 TokenA
         sell_order.locked = 100;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 200);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 800);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900);
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 200);
+        assert_eq!(vault.get_locked(alice, QUOTE), 800);
+        assert_eq!(vault.get_balance(bob, BASE), 900);
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the orders
         let (remaining_sell, events) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
+        let events = fills(events);
         assert!(remaining_sell.is_some()); // Sell order should not be filled
         assert!(events.is_empty()); // No fill events
 
@@ -524,14 +1653,14 @@ mod tests {
         assert_eq!(remaining_sell.price, 10);
 
         // Check final balances - should be unchanged
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 200);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 800);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900);
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 200);
+        assert_eq!(vault.get_locked(alice, QUOTE), 800);
+        assert_eq!(vault.get_balance(bob, BASE), 900);
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
@@ -540,58 +1669,51 @@ mod tests {
         let now = 1;
 
         // Alice places a buy order: 100 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 100, now);
-        vault.lock(alice, Token::Quote, 1000).unwrap(); // Lock 1000 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap(); // Lock 1000 TokenB
         buy_order.locked = 1000;
-        book.insert_new_order(buy_order.clone());
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob places two sell orders: 60 TokenA and 40 TokenA at price 10 TokenB
-        let mut sell_order1 = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            60,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 60).unwrap(); // Lock 60 TokenA
+        let mut sell_order1 = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 60, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 60).unwrap(); // Lock 60 TokenA
         sell_order1.locked = 60;
-        book.insert_new_order(sell_order1.clone());
-
-        let mut sell_order2 = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            40,
-            now + 2,
-        );
-        vault.lock(bob, Token::Base, 40).unwrap(); // Lock 40 TokenA
+        book.insert_new_order(sell_order1.clone()).unwrap();
+
+        let mut sell_order2 = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 40, now + 2)
+            .unwrap();
+        vault.lock(bob, BASE, 40).unwrap(); // Lock 40 TokenA
         sell_order2.locked = 40;
-        book.insert_new_order(sell_order2.clone());
+        book.insert_new_order(sell_order2.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 1000);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900);
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 1000);
+        assert_eq!(vault.get_balance(bob, BASE), 900);
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the first sell order
         let (remaining_sell1, events1) = book
-            .match_buy_orders(sell_order1.clone(), &mut vault)
+            .match_buy_orders(sell_order1.clone(), None, &mut vault)
             .unwrap();
+        let events1 = fills(events1);
         assert!(remaining_sell1.is_none()); // First sell order should be fully filled
         assert_eq!(events1.len(), 2); // Two fill events
 
         // Match the second sell order
         let (remaining_sell2, events2) = book
-            .match_buy_orders(sell_order2.clone(), &mut vault)
+            .match_buy_orders(sell_order2.clone(), None, &mut vault)
             .unwrap();
+        let events2 = fills(events2);
         assert!(remaining_sell2.is_none()); // Second sell order should be fully filled
         assert_eq!(events2.len(), 2); // Two fill events
 
@@ -613,14 +1735,14 @@ mod tests {
         assert_eq!(sell_event2.filled_qty, 40);
 
         // Check final balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1100); // Received 100 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0); // Spent 1000 TokenB
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900); // Spent 100 TokenA
-        assert_eq!(vault.get_locked(bob, Token::Base), 0);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 2000); // Received 1000 TokenB
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1100); // Received 100 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0); // Spent 1000 TokenB
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(bob, BASE), 900); // Spent 100 TokenA
+        assert_eq!(vault.get_locked(bob, BASE), 0);
+        assert_eq!(vault.get_balance(bob, QUOTE), 2000); // Received 1000 TokenB
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
@@ -629,39 +1751,36 @@ mod tests {
         let now = 1;
 
         // Alice places a buy order: 100 TokenA at price 8 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 8, 100, now);
-        vault.lock(alice, Token::Quote, 800).unwrap(); // Lock 800 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 8, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 800).unwrap(); // Lock 800 TokenB
         buy_order.locked = 800;
-        book.insert_new_order(buy_order.clone());
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob places a sell order: 100 TokenA at price 6 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            6,
-            100,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 100).unwrap(); // Lock 100 TokenA
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 6, 100, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap(); // Lock 100 TokenA
         sell_order.locked = 100;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 200);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 800);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900);
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 200);
+        assert_eq!(vault.get_locked(alice, QUOTE), 800);
+        assert_eq!(vault.get_balance(bob, BASE), 900);
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the orders
         let (remaining_sell, events) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
+        let events = fills(events);
         assert!(remaining_sell.is_none()); // Sell order should be fully filled
         assert_eq!(events.len(), 2); // Two fill events
 
@@ -669,20 +1788,20 @@ mod tests {
         let (buy_event, sell_event) = (&events[0], &events[1]);
         assert_eq!(buy_event.order_id, buy_order.id);
         assert_eq!(sell_event.order_id, sell_order.id);
-        assert_eq!(buy_event.filled_price, 6); // Should match at sell price
-        assert_eq!(sell_event.filled_price, 6);
+        assert_eq!(buy_event.filled_price, 8); // Should match at the resting buy price
+        assert_eq!(sell_event.filled_price, 8);
         assert_eq!(buy_event.filled_qty, 100);
         assert_eq!(sell_event.filled_qty, 100);
 
         // Check final balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1100); // Received 100 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 400); // Spent 600 TokenB (at sell price), 200 TokenB unlocked
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900); // Spent 100 TokenA
-        assert_eq!(vault.get_locked(bob, Token::Base), 0);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1600); // Received 600 TokenB
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1100); // Received 100 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 200); // Spent 800 TokenB (at buy price)
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(bob, BASE), 900); // Spent 100 TokenA
+        assert_eq!(vault.get_locked(bob, BASE), 0);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1800); // Received 800 TokenB
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
@@ -691,33 +1810,36 @@ mod tests {
         let now = 1;
 
         // Alice places a buy order: 100 TokenA at price 8 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 8, 100, now);
-        vault.lock(alice, Token::Quote, 800).unwrap(); // Lock 800 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 8, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 800).unwrap(); // Lock 800 TokenB
         buy_order.locked = 800;
-        book.insert_new_order(buy_order.clone());
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob places a sell order: 50 TokenA at price 6 TokenB
-        let mut sell_order =
-            book.make_new_order(bob, (Token::Base, Token::Quote), Side::Sell, 6, 50, now + 1);
-        vault.lock(bob, Token::Base, 50).unwrap(); // Lock 50 TokenA
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 6, 50, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 50).unwrap(); // Lock 50 TokenA
         sell_order.locked = 50;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 200);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 800);
-        assert_eq!(vault.get_balance(bob, Token::Base), 950);
-        assert_eq!(vault.get_locked(bob, Token::Base), 50);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 200);
+        assert_eq!(vault.get_locked(alice, QUOTE), 800);
+        assert_eq!(vault.get_balance(bob, BASE), 950);
+        assert_eq!(vault.get_locked(bob, BASE), 50);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the orders
         let (remaining_sell, events) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
+        let events = fills(events);
         assert!(remaining_sell.is_none()); // Sell order should be fully filled
         assert_eq!(events.len(), 2); // Two fill events
 
@@ -725,20 +1847,20 @@ mod tests {
         let (buy_event, sell_event) = (&events[0], &events[1]);
         assert_eq!(buy_event.order_id, buy_order.id);
         assert_eq!(sell_event.order_id, sell_order.id);
-        assert_eq!(buy_event.filled_price, 6); // Should match at sell price
-        assert_eq!(sell_event.filled_price, 6);
+        assert_eq!(buy_event.filled_price, 8); // Should match at the resting buy price
+        assert_eq!(sell_event.filled_price, 8);
         assert_eq!(buy_event.filled_qty, 50);
         assert_eq!(sell_event.filled_qty, 50);
 
         // Check final balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1050); // Received 50 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 200);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 500); // Spent 300 TokenB (at sell price), 500 TokenB trasnfer
-        assert_eq!(vault.get_balance(bob, Token::Base), 950); // Spent 50 TokenA
-        assert_eq!(vault.get_locked(bob, Token::Base), 0);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1300); // Received 300 TokenB
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1050); // Received 50 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 200);
+        assert_eq!(vault.get_locked(alice, QUOTE), 400); // Spent 400 TokenB (at buy price), 400 TokenB still locked
+        assert_eq!(vault.get_balance(bob, BASE), 950); // Spent 50 TokenA
+        assert_eq!(vault.get_locked(bob, BASE), 0);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1400); // Received 400 TokenB
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
@@ -747,51 +1869,43 @@ mod tests {
         let now = 1;
 
         // Alice places two buy orders: 60 TokenA at price 10 TokenB and 40 TokenA at price 10 TokenB
-        let mut buy_order1 =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 60, now);
-        vault.lock(alice, Token::Quote, 600).unwrap(); // Lock 600 TokenB
+        let mut buy_order1 = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 60, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 600).unwrap(); // Lock 600 TokenB
         buy_order1.locked = 600;
-        book.insert_new_order(buy_order1.clone());
-
-        let mut buy_order2 = book.make_new_order(
-            alice,
-            (Token::Base, Token::Quote),
-            Side::Buy,
-            10,
-            40,
-            now + 1,
-        );
-        vault.lock(alice, Token::Quote, 400).unwrap(); // Lock 400 TokenB
+        book.insert_new_order(buy_order1.clone()).unwrap();
+
+        let mut buy_order2 = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 40, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 400).unwrap(); // Lock 400 TokenB
         buy_order2.locked = 400;
-        book.insert_new_order(buy_order2.clone());
+        book.insert_new_order(buy_order2.clone()).unwrap();
 
         // Bob places a sell order: 100 TokenA at price 10 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            100,
-            now + 2,
-        );
-        vault.lock(bob, Token::Base, 100).unwrap(); // Lock 100 TokenA
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now + 2)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap(); // Lock 100 TokenA
         sell_order.locked = 100;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 1000);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900);
-        assert_eq!(vault.get_locked(bob, Token::Base), 100);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 1000);
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 1000);
+        assert_eq!(vault.get_balance(bob, BASE), 900);
+        assert_eq!(vault.get_locked(bob, BASE), 100);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000);
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
 
         // Match the sell order against both buy orders
         let (remaining_sell, events) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
+        let events = fills(events);
         assert!(remaining_sell.is_none()); // Sell order should be fully filled
         assert_eq!(events.len(), 4); // Four fill events (two for each match)
 
@@ -813,133 +1927,360 @@ mod tests {
         assert_eq!(sell_event2.filled_qty, 40);
 
         // Check final balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1100); // Received 100 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0); // Spent 1000 TokenB
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
-        assert_eq!(vault.get_balance(bob, Token::Base), 900); // Spent 100 TokenA
-        assert_eq!(vault.get_locked(bob, Token::Base), 0);
-        assert_eq!(vault.get_balance(bob, Token::Quote), 2000); // Received 1000 TokenB
-        assert_eq!(vault.get_locked(bob, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1100); // Received 100 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0); // Spent 1000 TokenB
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(bob, BASE), 900); // Spent 100 TokenA
+        assert_eq!(vault.get_locked(bob, BASE), 0);
+        assert_eq!(vault.get_balance(bob, QUOTE), 2000); // Received 1000 TokenB
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
     }
 
     #[test]
-    fn test_cancel_unmatched_order() {
-        let (mut book, mut vault, alice, _) = setup();
+    fn test_buy_capped_by_locked_quote() {
+        let (mut book, mut vault, alice, bob) = setup();
         let now = 1;
 
-        // Alice places a buy order: 100 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 100, now);
-        vault.lock(alice, Token::Quote, 1000).unwrap(); // Lock 1000 TokenB
-        buy_order.locked = 1000;
-        book.insert_new_order(buy_order.clone());
-
-        // Check initial balances
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 1000);
-
-        // Cancel the order
-        book.cancel_order(alice, buy_order.id, &mut vault).unwrap();
+        // Bob places two sell orders: 40 TokenA at price 10 and 60 TokenA at price 20
+        let mut sell_order1 = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 40, now)
+            .unwrap();
+        vault.lock(bob, BASE, 40).unwrap();
+        sell_order1.locked = 40;
+        book.insert_new_order(sell_order1.clone()).unwrap();
 
-        // Check final balances - all locked tokens should be unlocked
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 1000); // All TokenB unlocked
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
+        let mut sell_order2 = book
+            .make_new_order(bob, PAIR, Side::Sell, 20, 60, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 60).unwrap();
+        sell_order2.locked = 60;
+        book.insert_new_order(sell_order2.clone()).unwrap();
 
-        // Try to cancel again - should fail
-        assert!(matches!(
-            book.cancel_order(alice, buy_order.id, &mut vault),
-            Err(Error::OrderNotFound(_))
-        ));
+        // Alice buys with 805 TokenB regardless of quantity
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, u128::MAX, u128::MAX, now + 2)
+            .unwrap();
+        vault.lock(alice, QUOTE, 805).unwrap();
+        buy_order.locked = 805;
+
+        let (remaining_buy, events) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+
+        let events = fills(events);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0].order_id, sell_order1.id);
+        assert_eq!(events[0].filled_qty, 40);
+        assert_eq!(events[2].order_id, sell_order2.id);
+        assert_eq!(events[2].filled_price, 20);
+        assert_eq!(events[2].filled_qty, 20); // (805 - 400) / 20
+
+        // 5 TokenB left cannot buy anything more
+        let remaining_buy = remaining_buy.unwrap();
+        assert_eq!(remaining_buy.locked, 5);
+        assert_eq!(vault.get_balance(alice, BASE), 1060);
+        assert_eq!(vault.get_locked(alice, QUOTE), 5);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1800);
+        assert_eq!(vault.get_locked(bob, BASE), 40);
     }
 
     #[test]
-    fn test_cancel_partially_filled_order() {
+    fn test_modify_order_keeps_time_in_force_and_max_fills() {
         let (mut book, mut vault, alice, bob) = setup();
         let now = 1;
 
-        // Alice places a buy order: 100 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 100, now);
-        vault.lock(alice, Token::Quote, 1000).unwrap(); // Lock 1000 TokenB
-        buy_order.locked = 1000;
-        book.insert_new_order(buy_order.clone());
-
-        // Bob places a sell order: 50 TokenA at price 10 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            50,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 50).unwrap(); // Lock 50 TokenA
-        sell_order.locked = 50;
-        book.insert_new_order(sell_order.clone());
+        // Bob asks 5 TokenA at price 10, 11 and 12
+        for price in [10, 11, 12] {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, price, 5, now)
+                .unwrap();
+            vault.lock(bob, BASE, 5).unwrap();
+            sell_order.locked = 5;
+            book.insert_new_order(sell_order).unwrap();
+        }
 
-        // Match the orders
-        let (remaining_sell, _) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
-            .unwrap();
-        assert!(remaining_sell.is_none()); // Sell order should be fully filled
+        // Alice bids 10 TokenA at price 9 post-only, and at price 9 with one fill at a time
+        let mut ids = Vec::new();
+        for (time_in_force, max_fills) in [
+            (TimeInForce::PostOnly, None),
+            (TimeInForce::GoodTillCancel, Some(1)),
+        ] {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, 9, 10, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, 90).unwrap();
+            buy_order.locked = 90;
+            buy_order.time_in_force = time_in_force;
+            buy_order.max_fills = max_fills;
+            ids.push(buy_order.id);
+            book.insert_new_order(buy_order).unwrap();
+        }
 
-        // Check balances after partial fill
-        assert_eq!(vault.get_balance(alice, Token::Base), 1050); // Received 50 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 500); // 500 TokenB still locked
+        // The post-only bid may not move to a matching price, and stays as it was
+        assert!(matches!(
+            book.modify_order(alice, ids[0], 10, 10, now + 1, &mut vault),
+            Err(Error::PostOnlyWouldCross)
+        ));
+        let order = book.get_order(ids[0]).unwrap();
+        assert_eq!((order.price, order.qty, order.locked), (9, 10, 90));
+        assert_eq!(vault.get_locked(alice, QUOTE), 180);
 
-        // Cancel the partially filled buy order
-        book.cancel_order(alice, buy_order.id, &mut vault).unwrap();
+        // Raising the other bid to 12 matches one ask, then waits for the crank
+        let (remaining, events) = book
+            .modify_order(alice, ids[1], 12, 10, now + 1, &mut vault)
+            .unwrap();
+        assert_eq!(fills(events).len(), 2);
+        assert_eq!(remaining.unwrap().qty, 5);
+        assert!(book.is_pending(ids[1]));
+        assert_eq!(book.depth(PAIR, 5, now + 1).unwrap().bids, vec![(9, 10)]);
 
-        // Check final balances - remaining locked tokens should be unlocked
-        assert_eq!(vault.get_balance(alice, Token::Base), 1050); // Still have 50 TokenA from partial fill
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 500); // All remaining TokenB unlocked
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
+        let (settled, _) = book
+            .match_pending_orders(PAIR, now + 2, 10, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 1);
+        assert!(book.get_order(ids[1]).is_none());
+        assert_eq!(book.depth(PAIR, 5, now + 2).unwrap().asks, vec![(12, 5)]);
     }
 
     #[test]
-    fn test_cancel_fully_filled_order() {
+    fn test_would_cross_and_fillable_qty() {
         let (mut book, mut vault, alice, bob) = setup();
         let now = 1;
 
-        // Alice places a buy order: 100 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 100, now);
-        vault.lock(alice, Token::Quote, 1000).unwrap(); // Lock 1000 TokenB
+        // Bob places two sell orders: 30 TokenA at price 10 and 50 TokenA at price 12
+        let mut sell_order1 = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 30, now)
+            .unwrap();
+        vault.lock(bob, BASE, 30).unwrap();
+        sell_order1.locked = 30;
+        book.insert_new_order(sell_order1).unwrap();
+
+        let mut sell_order2 = book
+            .make_new_order(bob, PAIR, Side::Sell, 12, 50, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 50).unwrap();
+        sell_order2.locked = 50;
+        book.insert_new_order(sell_order2).unwrap();
+
+        // A buy below the best ask neither crosses nor fills
+        let buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 9, 100, now + 2)
+            .unwrap();
+        assert!(!book.would_cross(&buy_order).unwrap());
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 0);
+
+        // Only the levels within the buy price count
+        let buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 11, 100, now + 2)
+            .unwrap();
+        assert!(book.would_cross(&buy_order).unwrap());
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 30);
+
+        // Fillable quantity is capped by the order quantity
+        let buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 12, 60, now + 2)
+            .unwrap();
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 60);
+
+        // Sells look at the bid side, which is empty
+        let sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 1, 10, now + 2)
+            .unwrap();
+        assert!(!book.would_cross(&sell_order).unwrap());
+        assert_eq!(book.fillable_qty(&sell_order).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_fillable_qty_with_self_trade_prevention() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob sells 30 TokenA at price 10, then Alice 20 at price 10 and Bob 50 at price 11
+        let mut ask_ids = Vec::new();
+        for (owner, price, qty) in [(bob, 10, 30), (alice, 10, 20), (bob, 11, 50)] {
+            let mut sell_order = book
+                .make_new_order(owner, PAIR, Side::Sell, price, qty, now)
+                .unwrap();
+            vault.lock(owner, BASE, qty).unwrap();
+            sell_order.locked = qty;
+            ask_ids.push(sell_order.id);
+            book.insert_new_order(sell_order).unwrap();
+        }
+
+        // Alice's own ask stops her buy short, unless it gets cancelled
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 11, 60, now + 1)
+            .unwrap();
+        for stp in [
+            SelfTradePrevention::CancelNewest,
+            SelfTradePrevention::CancelBoth,
+            SelfTradePrevention::Decrement,
+        ] {
+            buy_order.stp = stp;
+            assert_eq!(book.fillable_qty(&buy_order).unwrap(), 30);
+        }
+        buy_order.stp = SelfTradePrevention::CancelOldest;
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 60);
+
+        // and matching agrees: a fill-or-kill of 60 goes through in full
+        vault.lock(alice, QUOTE, 660).unwrap();
+        buy_order.locked = 660;
+        let (remaining_buy, events) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(remaining_buy.is_none());
+        assert_eq!(cancels(&events), vec![ask_ids[1]]);
+        assert_eq!(
+            fills(events)
+                .iter()
+                .map(|fill| fill.filled_qty)
+                .sum::<u128>(),
+            120
+        );
+    }
+
+    #[test]
+    fn test_discard_order() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob sells 40 TokenA at price 10
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 40, now)
+            .unwrap();
+        vault.lock(bob, BASE, 40).unwrap();
+        sell_order.locked = 40;
+        book.insert_new_order(sell_order).unwrap();
+
+        // Alice buys 100 TokenA at price 10, only 40 fill
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap();
         buy_order.locked = 1000;
-        book.insert_new_order(buy_order.clone());
+        let (remaining_buy, _) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        let remaining_buy = remaining_buy.unwrap();
+        assert_eq!(remaining_buy.qty, 60);
+        assert_eq!(vault.get_locked(alice, QUOTE), 600);
+
+        // Discarding the remainder unlocks it without touching the book
+        book.discard_order(remaining_buy.clone(), &mut vault)
+            .unwrap();
+        assert_eq!(vault.get_balance(alice, QUOTE), 600);
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert!(matches!(
+            book.cancel_order(alice, remaining_buy.id, &mut vault),
+            Err(Error::OrderNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_cancel_unmatched_order() {
+        let (mut book, mut vault, alice, _) = setup();
+        let now = 1;
+
+        // Alice places a buy order: 100 TokenA at price 10 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap(); // Lock 1000 TokenB
+        buy_order.locked = 1000;
+        book.insert_new_order(buy_order.clone()).unwrap();
+
+        // Check initial balances
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 1000);
+
+        // Cancel the order
+        book.cancel_order(alice, buy_order.id, &mut vault).unwrap();
+
+        // Check final balances - all locked tokens should be unlocked
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 1000); // All TokenB unlocked
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+
+        // Try to cancel again - should fail
+        assert!(matches!(
+            book.cancel_order(alice, buy_order.id, &mut vault),
+            Err(Error::OrderNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_cancel_partially_filled_order() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice places a buy order: 100 TokenA at price 10 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap(); // Lock 1000 TokenB
+        buy_order.locked = 1000;
+        book.insert_new_order(buy_order.clone()).unwrap();
+
+        // Bob places a sell order: 50 TokenA at price 10 TokenB
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 50, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 50).unwrap(); // Lock 50 TokenA
+        sell_order.locked = 50;
+        book.insert_new_order(sell_order.clone()).unwrap();
+
+        // Match the orders
+        let (remaining_sell, _) = book
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
+            .unwrap();
+        assert!(remaining_sell.is_none()); // Sell order should be fully filled
+
+        // Check balances after partial fill
+        assert_eq!(vault.get_balance(alice, BASE), 1050); // Received 50 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 500); // 500 TokenB still locked
+
+        // Cancel the partially filled buy order
+        book.cancel_order(alice, buy_order.id, &mut vault).unwrap();
+
+        // Check final balances - remaining locked tokens should be unlocked
+        assert_eq!(vault.get_balance(alice, BASE), 1050); // Still have 50 TokenA from partial fill
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 500); // All remaining TokenB unlocked
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+    }
+
+    #[test]
+    fn test_cancel_fully_filled_order() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice places a buy order: 100 TokenA at price 10 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap(); // Lock 1000 TokenB
+        buy_order.locked = 1000;
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob places a sell order: 100 TokenA at price 10 TokenB
-        let mut sell_order = book.make_new_order(
-            bob,
-            (Token::Base, Token::Quote),
-            Side::Sell,
-            10,
-            100,
-            now + 1,
-        );
-        vault.lock(bob, Token::Base, 100).unwrap(); // Lock 100 TokenA
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap(); // Lock 100 TokenA
         sell_order.locked = 100;
-        book.insert_new_order(sell_order.clone());
+        book.insert_new_order(sell_order.clone()).unwrap();
 
         // Match the orders
         let (remaining_sell, _) = book
-            .match_buy_orders(sell_order.clone(), &mut vault)
+            .match_buy_orders(sell_order.clone(), None, &mut vault)
             .unwrap();
         assert!(remaining_sell.is_none()); // Sell order should be fully filled
 
         // Check balances after full fill
-        assert_eq!(vault.get_balance(alice, Token::Base), 1100); // Received 100 TokenA
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0); // Spent all TokenB
-        assert_eq!(vault.get_locked(alice, Token::Quote), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1100); // Received 100 TokenA
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0); // Spent all TokenB
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
 
         // Try to cancel the fully filled order - should fail
         assert!(matches!(
@@ -954,22 +2295,1464 @@ mod tests {
         let now = 1;
 
         // Alice places a buy order: 100 TokenA at price 10 TokenB
-        let mut buy_order =
-            book.make_new_order(alice, (Token::Base, Token::Quote), Side::Buy, 10, 100, now);
-        vault.lock(alice, Token::Quote, 1000).unwrap(); // Lock 1000 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap(); // Lock 1000 TokenB
         buy_order.locked = 1000;
-        book.insert_new_order(buy_order.clone());
+        book.insert_new_order(buy_order.clone()).unwrap();
 
         // Bob tries to cancel Alice's order - should fail
         assert!(matches!(
             book.cancel_order(bob, buy_order.id, &mut vault),
-            Err(Error::Unauthorized(_))
+            Err(Error::NotOrderOwner)
         ));
 
         // Check balances - should be unchanged
-        assert_eq!(vault.get_balance(alice, Token::Base), 1000);
-        assert_eq!(vault.get_locked(alice, Token::Base), 0);
-        assert_eq!(vault.get_balance(alice, Token::Quote), 0);
-        assert_eq!(vault.get_locked(alice, Token::Quote), 1000);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 1000);
+    }
+
+    #[test]
+    fn test_add_market() {
+        let (mut book, _, _, _) = setup();
+        let other: Pair = (BASE, AccountId([0xd; 32]));
+
+        // Listing an existing or degenerate pair should fail
+        assert!(matches!(book.add_market(PAIR), Err(Error::MarketExists)));
+        assert!(matches!(
+            book.add_market((BASE, BASE)),
+            Err(Error::IdenticalTokens)
+        ));
+
+        // A new pair is listed independently of its reverse
+        assert!(!book.has_market(other));
+        book.add_market(other).unwrap();
+        assert!(book.has_market(other));
+        assert!(!book.has_market((other.1, other.0)));
+    }
+
+    #[test]
+    fn test_markets_are_isolated() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let other_quote = AccountId([0xd; 32]);
+        let other: Pair = (BASE, other_quote);
+        book.add_market(other).unwrap();
+        vault.deposit(alice, other_quote, 1000).unwrap();
+        let now = 1;
+
+        // Alice places a buy order on the other market: 100 BASE at price 10
+        let mut buy_order = book
+            .make_new_order(alice, other, Side::Buy, 10, 100, now)
+            .unwrap();
+        vault.lock(alice, other_quote, 1000).unwrap();
+        buy_order.locked = 1000;
+        book.insert_new_order(buy_order.clone()).unwrap();
+
+        // Bob sells 100 BASE at price 10 on the default market
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap();
+        sell_order.locked = 100;
+
+        // Orders on different markets never match
+        let (remaining_sell, events) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        let events = fills(events);
+        assert!(remaining_sell.is_some());
+        assert!(events.is_empty());
+
+        // Orders on an unknown market are rejected
+        let unknown = book
+            .make_new_order(alice, (QUOTE, BASE), Side::Buy, 10, 100, now)
+            .unwrap();
+        assert!(matches!(
+            book.insert_new_order(unknown),
+            Err(Error::PairNotSupported)
+        ));
+    }
+
+    #[test]
+    fn test_expired_order_skipped_during_matching() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob sells 50 TokenA at price 10 until 5, and 50 TokenA at price 11 without expiry
+        let mut sell_order1 = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 50, now)
+            .unwrap();
+        vault.lock(bob, BASE, 50).unwrap();
+        sell_order1.locked = 50;
+        sell_order1.expires_at = Some(5);
+        book.insert_new_order(sell_order1.clone()).unwrap();
+
+        let mut sell_order2 = book
+            .make_new_order(bob, PAIR, Side::Sell, 11, 50, now)
+            .unwrap();
+        vault.lock(bob, BASE, 50).unwrap();
+        sell_order2.locked = 50;
+        book.insert_new_order(sell_order2.clone()).unwrap();
+
+        // Alice buys 50 TokenA at price 11 once the first sell order expired
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 11, 50, 5)
+            .unwrap();
+        vault.lock(alice, QUOTE, 550).unwrap();
+        buy_order.locked = 550;
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 50);
+
+        let (remaining_buy, events) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(remaining_buy.is_none());
+        assert_eq!(events.len(), 4);
+        assert!(matches!(
+            events[0],
+            BookEvent::Expired(EventExpired { order_id }) if order_id == sell_order1.id
+        ));
+        let events = fills(events);
+        assert_eq!(events[0].order_id, sell_order2.id);
+        assert_eq!(events[0].filled_price, 11);
+
+        // The expired sell order is gone and its TokenA unlocked
+        assert_eq!(vault.get_balance(bob, BASE), 950);
+        assert_eq!(vault.get_locked(bob, BASE), 0);
+        assert!(matches!(
+            book.cancel_order(bob, sell_order1.id, &mut vault),
+            Err(Error::OrderNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_remove_expired_orders() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice buys 10 TokenA at price 10 until 3, 5 and 10
+        let mut buy_ids = Vec::new();
+        for expires_at in [3, 5, 10] {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, 10, 10, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, 100).unwrap();
+            buy_order.locked = 100;
+            buy_order.expires_at = Some(expires_at);
+            book.insert_new_order(buy_order.clone()).unwrap();
+            buy_ids.push(buy_order.id);
+        }
+
+        // Bob sells 10 TokenA at price 12 until 4, then cancels it
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 12, 10, now)
+            .unwrap();
+        vault.lock(bob, BASE, 10).unwrap();
+        sell_order.locked = 10;
+        sell_order.expires_at = Some(4);
+        book.insert_new_order(sell_order.clone()).unwrap();
+        book.cancel_order(bob, sell_order.id, &mut vault).unwrap();
+
+        // Nothing is expired yet
+        let events = book.remove_expired_orders(PAIR, 2, 10, &mut vault).unwrap();
+        assert!(events.is_empty());
+
+        // Sweep at 6 in batches of one
+        let events = book.remove_expired_orders(PAIR, 6, 1, &mut vault).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            BookEvent::Expired(EventExpired { order_id }) if order_id == buy_ids[0]
+        ));
+        let events = book.remove_expired_orders(PAIR, 6, 10, &mut vault).unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            events[0],
+            BookEvent::Expired(EventExpired { order_id }) if order_id == buy_ids[1]
+        ));
+
+        // Expired orders are unlocked, the last one still rests
+        assert_eq!(vault.get_balance(alice, QUOTE), 900);
+        assert_eq!(vault.get_locked(alice, QUOTE), 100);
+        book.cancel_order(alice, buy_ids[2], &mut vault).unwrap();
+        assert!(book
+            .remove_expired_orders(PAIR, 20, 10, &mut vault)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_depth() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Empty book
+        let depth = book.depth(PAIR, 5, now).unwrap();
+        assert_eq!(depth, Depth::default());
+
+        // Alice bids 10 @ 9, 20 @ 9, 5 @ 8 and 7 @ 7
+        for (price, qty) in [(9, 10), (9, 20), (8, 5), (7, 7)] {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, price, qty, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, price * qty).unwrap();
+            buy_order.locked = price * qty;
+            book.insert_new_order(buy_order).unwrap();
+        }
+
+        // Bob asks 15 @ 11, 5 @ 12 until 3, 25 @ 12
+        for (price, qty, expires_at) in [(11, 15, None), (12, 5, Some(3)), (12, 25, None)] {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, price, qty, now)
+                .unwrap();
+            vault.lock(bob, BASE, qty).unwrap();
+            sell_order.locked = qty;
+            sell_order.expires_at = expires_at;
+            book.insert_new_order(sell_order).unwrap();
+        }
+
+        let depth = book.depth(PAIR, 2, now).unwrap();
+        assert_eq!(depth.best_bid, Some(9));
+        assert_eq!(depth.best_ask, Some(11));
+        assert_eq!(depth.bids, vec![(9, 30), (8, 5)]);
+        assert_eq!(depth.asks, vec![(11, 15), (12, 30)]);
+
+        // Expired orders are left out
+        let depth = book.depth(PAIR, 5, 3).unwrap();
+        assert_eq!(depth.bids, vec![(9, 30), (8, 5), (7, 7)]);
+        assert_eq!(depth.asks, vec![(11, 15), (12, 25)]);
+
+        // Zero levels still report the top of book
+        let depth = book.depth(PAIR, 0, now).unwrap();
+        assert_eq!(depth.best_bid, Some(9));
+        assert_eq!(depth.best_ask, Some(11));
+        assert!(depth.bids.is_empty());
+        assert!(depth.asks.is_empty());
+    }
+
+    #[test]
+    fn test_get_order_and_open_orders() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice bids 10 @ 12, 10 @ 11 and 10 @ 10
+        let mut ids = Vec::new();
+        for price in [12, 11, 10] {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, price, 10, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, price * 10).unwrap();
+            buy_order.locked = price * 10;
+            ids.push(buy_order.id);
+            book.insert_new_order(buy_order).unwrap();
+        }
+        assert_eq!(book.get_order(ids[0]).unwrap().qty, 10);
+        assert_eq!(book.open_orders_of(alice, 0, 10).len(), 3);
+        assert!(book.open_orders_of(bob, 0, 10).is_empty());
+
+        // Pagination
+        let page = book.open_orders_of(alice, 1, 1);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].id, ids[1]);
+        assert!(book.open_orders_of(alice, 3, 10).is_empty());
+
+        // Bob sells 15 @ 11: the first bid is filled, the second one partially
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 11, 15, now + 1)
+            .unwrap();
+        vault.lock(bob, BASE, 15).unwrap();
+        sell_order.locked = 15;
+        let (remaining_sell, _) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        assert!(remaining_sell.is_none());
+
+        assert!(book.get_order(ids[0]).is_none());
+        let order = book.get_order(ids[1]).unwrap();
+        assert_eq!(order.qty, 5);
+        assert_eq!(order.locked, 55);
+        let open: Vec<u64> = book
+            .open_orders_of(alice, 0, 10)
+            .iter()
+            .map(|o| o.id)
+            .collect();
+        assert_eq!(open, vec![ids[1], ids[2]]);
+
+        // Alice buys 4 of Bob's ask of 10 @ 20, the rest stays locked
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 20, 10, now + 2)
+            .unwrap();
+        vault.lock(bob, BASE, 10).unwrap();
+        sell_order.locked = 10;
+        let ask_id = sell_order.id;
+        book.insert_new_order(sell_order).unwrap();
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 20, 4, now + 3)
+            .unwrap();
+        vault.lock(alice, QUOTE, 80).unwrap();
+        buy_order.locked = 80;
+        let (remaining_buy, _) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(remaining_buy.is_none());
+
+        let order = book.get_order(ask_id).unwrap();
+        assert_eq!(order.qty, 6);
+        assert_eq!(order.locked, 6);
+        assert_eq!(vault.get_locked(bob, BASE), 6);
+        assert_eq!(book.open_orders_of(bob, 0, 10), vec![order]);
+
+        // Cancelled orders leave the index
+        book.cancel_order(alice, ids[1], &mut vault).unwrap();
+        book.cancel_order(alice, ids[2], &mut vault).unwrap();
+        assert!(book.get_order(ids[2]).is_none());
+        assert!(book.open_orders_of(alice, 0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_fees() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        assert!(matches!(
+            book.set_fee_rates(10_001, 0),
+            Err(Error::FeeRateTooHigh)
+        ));
+        // 1% maker, 2% taker
+        book.set_fee_rates(100, 200).unwrap();
+        assert_eq!(book.fee_rates(), (100, 200));
+
+        // Bob rests a sell order: 100 TokenA at price 10
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now)
+            .unwrap();
+        vault.lock(bob, BASE, 100).unwrap();
+        sell_order.locked = 100;
+        book.insert_new_order(sell_order.clone()).unwrap();
+
+        // Alice takes it: 100 TokenA for 1000 TokenB
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 100, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 1000).unwrap();
+        buy_order.locked = 1000;
+        let (_, events) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        let events = fills(events);
+        assert_eq!(events[0].order_id, sell_order.id);
+        assert_eq!(events[0].fee, 10); // maker fee in TokenB
+        assert_eq!(events[1].fee, 2); // taker fee in TokenA
+
+        assert_eq!(vault.get_balance(alice, BASE), 1098);
+        assert_eq!(vault.get_balance(bob, QUOTE), 1990);
+        assert_eq!(vault.get_fees(BASE), 2);
+        assert_eq!(vault.get_fees(QUOTE), 10);
+
+        // Bob rests a buy order: 50 TokenA at price 10
+        let mut buy_order = book
+            .make_new_order(bob, PAIR, Side::Buy, 10, 50, now + 2)
+            .unwrap();
+        vault.lock(bob, QUOTE, 500).unwrap();
+        buy_order.locked = 500;
+        book.insert_new_order(buy_order).unwrap();
+
+        // Alice takes it, the maker fee of 0.5 TokenA rounds down to 0
+        let mut sell_order = book
+            .make_new_order(alice, PAIR, Side::Sell, 10, 50, now + 3)
+            .unwrap();
+        vault.lock(alice, BASE, 50).unwrap();
+        sell_order.locked = 50;
+        let (_, events) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        let events = fills(events);
+        assert_eq!(events[0].fee, 0);
+        assert_eq!(events[1].fee, 10);
+
+        assert_eq!(vault.get_balance(bob, BASE), 950);
+        assert_eq!(vault.get_balance(alice, QUOTE), 490);
+        assert_eq!(vault.get_fees(BASE), 2);
+        assert_eq!(vault.get_fees(QUOTE), 20);
+    }
+
+    #[test]
+    fn test_price_level_fifo() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob sells 10, 20, 30 and 40 TokenA at price 10
+        let mut ids = Vec::new();
+        for qty in [10, 20, 30, 40] {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, 10, qty, now)
+                .unwrap();
+            vault.lock(bob, BASE, qty).unwrap();
+            sell_order.locked = qty;
+            ids.push(sell_order.id);
+            book.insert_new_order(sell_order).unwrap();
+        }
+        assert_eq!(book.depth(PAIR, 1, now).unwrap().asks, vec![(10, 100)]);
+
+        // Cancelling from the middle and the back keeps the queue linked
+        book.cancel_order(bob, ids[1], &mut vault).unwrap();
+        book.cancel_order(bob, ids[3], &mut vault).unwrap();
+        assert_eq!(book.depth(PAIR, 1, now).unwrap().asks, vec![(10, 40)]);
+
+        // Alice buys 15 TokenA: the oldest order fills first
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 15, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 150).unwrap();
+        buy_order.locked = 150;
+        let (remaining_buy, events) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(remaining_buy.is_none());
+        let events = fills(events);
+        assert_eq!(events[0].order_id, ids[0]);
+        assert_eq!(events[0].filled_qty, 10);
+        assert_eq!(events[2].order_id, ids[2]);
+        assert_eq!(events[2].filled_qty, 5);
+        assert_eq!(book.depth(PAIR, 1, now).unwrap().asks, vec![(10, 25)]);
+
+        // A new order queues behind the remaining one
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 5, now + 2)
+            .unwrap();
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        let last_id = sell_order.id;
+        book.insert_new_order(sell_order).unwrap();
+
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 30, now + 3)
+            .unwrap();
+        vault.lock(alice, QUOTE, 300).unwrap();
+        buy_order.locked = 300;
+        let (remaining_buy, events) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(remaining_buy.is_none());
+        let events = fills(events);
+        assert_eq!(events[0].order_id, ids[2]);
+        assert_eq!(events[2].order_id, last_id);
+
+        // The emptied level is gone
+        let depth = book.depth(PAIR, 1, now).unwrap();
+        assert_eq!(depth.best_ask, None);
+        assert!(depth.asks.is_empty());
+    }
+
+    #[test]
+    fn test_max_fills_and_pending_orders() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob sells 10 TokenA at each of price 10, 11 and 12
+        for price in [10, 11, 12] {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, price, 10, now)
+                .unwrap();
+            vault.lock(bob, BASE, 10).unwrap();
+            sell_order.locked = 10;
+            book.insert_new_order(sell_order).unwrap();
+        }
+
+        // Alice buys 40 TokenA at price 12, matching a single sell order
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 12, 40, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 480).unwrap();
+        buy_order.locked = 480;
+        let (remaining_buy, events) = book
+            .match_sell_orders(buy_order, Some(1), &mut vault)
+            .unwrap();
+        assert_eq!(fills(events).len(), 2);
+        let remaining_buy = remaining_buy.unwrap();
+        assert_eq!(remaining_buy.qty, 30);
+        assert!(book.would_cross(&remaining_buy).unwrap());
+
+        // The remainder waits off the book
+        let order_id = remaining_buy.id;
+        book.park_order(remaining_buy).unwrap();
+        assert_eq!(book.get_order(order_id).unwrap().qty, 30);
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().best_bid, None);
+
+        // Crank one fill at a time
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 2, 1, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 0);
+        assert_eq!(fills(events)[0].filled_price, 11);
+        assert_eq!(book.get_order(order_id).unwrap().qty, 20);
+
+        // Once it no longer crosses, the rest of the order rests on the book
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 3, 5, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 1);
+        assert_eq!(fills(events)[0].filled_price, 12);
+        assert_eq!(book.get_order(order_id).unwrap().qty, 10);
+        let depth = book.depth(PAIR, 5, now).unwrap();
+        assert_eq!(depth.bids, vec![(12, 10)]);
+        assert!(depth.asks.is_empty());
+        assert_eq!(vault.get_balance(alice, BASE), 1030);
+
+        // Nothing left to crank
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 4, 5, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 0);
+        assert!(events.is_empty());
+
+        // Pending orders can be cancelled
+        for _ in 0..2 {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, 13, 5, now + 5)
+                .unwrap();
+            vault.lock(bob, BASE, 5).unwrap();
+            sell_order.locked = 5;
+            book.insert_new_order(sell_order).unwrap();
+        }
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 13, 20, now + 6)
+            .unwrap();
+        vault.lock(alice, QUOTE, 260).unwrap();
+        buy_order.locked = 260;
+        let (remaining_buy, _) = book
+            .match_sell_orders(buy_order, Some(1), &mut vault)
+            .unwrap();
+        let remaining_buy = remaining_buy.unwrap();
+        let order_id = remaining_buy.id;
+        book.park_order(remaining_buy).unwrap();
+
+        let quote_locked = vault.get_locked(alice, QUOTE);
+        book.cancel_order(alice, order_id, &mut vault).unwrap();
+        assert_eq!(vault.get_locked(alice, QUOTE), quote_locked - 195);
+        assert!(book.get_order(order_id).is_none());
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 7, 5, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 0);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_crank_counts_makers_only() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice asks 5 at 9, Bob asks 5 at 10 twice
+        let mut ask_ids = Vec::new();
+        for (owner, price) in [(alice, 9), (bob, 10), (bob, 10)] {
+            let mut sell_order = book
+                .make_new_order(owner, PAIR, Side::Sell, price, 5, now)
+                .unwrap();
+            vault.lock(owner, BASE, 5).unwrap();
+            sell_order.locked = 5;
+            ask_ids.push(sell_order.id);
+            book.insert_new_order(sell_order).unwrap();
+        }
+
+        // Two pending bids of Alice, the first one cancels both on a self-trade
+        let mut pending_ids = Vec::new();
+        for stp in [
+            SelfTradePrevention::CancelBoth,
+            SelfTradePrevention::CancelNewest,
+        ] {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, 10, 5, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, 50).unwrap();
+            buy_order.locked = 50;
+            buy_order.stp = stp;
+            pending_ids.push(buy_order.id);
+            book.park_order(buy_order).unwrap();
+        }
+
+        // Two makers: Alice's own ask, then one of Bob's; the cancelled taker is not one
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 1, 2, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 2);
+        assert_eq!(cancels(&events), vec![ask_ids[0], pending_ids[0]]);
+        assert_eq!(fills(events)[0].order_id, ask_ids[1]);
+        assert!(book.get_order(pending_ids[1]).is_none());
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().asks, vec![(10, 5)]);
+    }
+
+    #[test]
+    fn test_crank_counts_orders_no_longer_crossing() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob asks 5 TokenA at price 10
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 5, now)
+            .unwrap();
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        book.insert_new_order(sell_order).unwrap();
+
+        // Four pending bids of Alice at price 9, below the ask by the time the crank runs
+        let mut pending_ids = Vec::new();
+        for _ in 0..4 {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, 9, 5, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, 45).unwrap();
+            buy_order.locked = 45;
+            pending_ids.push(buy_order.id);
+            book.park_order(buy_order).unwrap();
+        }
+
+        // Resting one without a fill still uses up the budget
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 1, 1, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 1);
+        assert!(events.is_empty());
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().bids, vec![(9, 5)]);
+        assert!(book.is_pending(pending_ids[1]));
+
+        let (settled, _) = book
+            .match_pending_orders(PAIR, now + 1, 3, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 3);
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().bids, vec![(9, 20)]);
+        assert!(!book.is_pending(pending_ids[3]));
+    }
+
+    #[test]
+    fn test_crank_cancels_immediate_or_cancel() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob asks 5 TokenA at price 10 twice
+        for _ in 0..2 {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, 10, 5, now)
+                .unwrap();
+            vault.lock(bob, BASE, 5).unwrap();
+            sell_order.locked = 5;
+            book.insert_new_order(sell_order).unwrap();
+        }
+
+        // Alice's pending bid for 15 at price 10 must not rest
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 15, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 150).unwrap();
+        buy_order.locked = 150;
+        buy_order.time_in_force = TimeInForce::ImmediateOrCancel;
+        let buy_id = buy_order.id;
+        book.park_order(buy_order).unwrap();
+
+        // It lifts both asks, then what is left is unlocked instead of resting
+        let (settled, events) = book
+            .match_pending_orders(PAIR, now + 1, 10, &mut vault)
+            .unwrap();
+        assert_eq!(settled, 1);
+        assert_eq!(cancels(&events), vec![buy_id]);
+        assert_eq!(fills(events).len(), 4);
+        assert!(book.get_order(buy_id).is_none());
+        assert!(book.depth(PAIR, 5, now + 1).unwrap().bids.is_empty());
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 900);
+    }
+
+    #[test]
+    fn test_self_trade_prevention() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+        let sell = |book: &mut BTreeOrderBook, vault: &mut Vault, owner, qty| {
+            let mut sell_order = book
+                .make_new_order(owner, PAIR, Side::Sell, 10, qty, now)
+                .unwrap();
+            vault.lock(owner, BASE, qty).unwrap();
+            sell_order.locked = qty;
+            book.insert_new_order(sell_order.clone()).unwrap();
+            sell_order.id
+        };
+        let buy = |book: &mut BTreeOrderBook, vault: &mut Vault, qty, stp| {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, 10, qty, now + 1)
+                .unwrap();
+            vault.lock(alice, QUOTE, 10 * qty).unwrap();
+            buy_order.locked = 10 * qty;
+            buy_order.stp = stp;
+            let taker_id = buy_order.id;
+            let (remaining_buy, events) = book.match_sell_orders(buy_order, None, vault).unwrap();
+            assert!(remaining_buy.is_none());
+            (taker_id, events)
+        };
+
+        // Alice sells 10 TokenA at price 10
+        let resting_a = sell(&mut book, &mut vault, alice, 10);
+
+        // Cancel newest: the incoming buy is dropped, its quote unlocked
+        let (taker_id, events) = buy(&mut book, &mut vault, 5, SelfTradePrevention::CancelNewest);
+        assert_eq!(cancels(&events), vec![taker_id]);
+        assert_eq!(book.get_order(resting_a).unwrap().qty, 10);
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+
+        // Decrement: both lose 5, the buy reaches zero and is cancelled
+        let (taker_id, events) = buy(&mut book, &mut vault, 5, SelfTradePrevention::Decrement);
+        assert_eq!(cancels(&events), vec![taker_id]);
+        assert_eq!(book.get_order(resting_a).unwrap().qty, 5);
+        assert_eq!(vault.get_locked(alice, BASE), 5);
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(book.depth(PAIR, 1, now).unwrap().asks, vec![(10, 5)]);
+
+        // Cancel oldest: the resting sell is dropped and matching goes on with Bob's
+        let resting_b = sell(&mut book, &mut vault, bob, 10);
+        let (_, events) = buy(&mut book, &mut vault, 8, SelfTradePrevention::CancelOldest);
+        assert_eq!(cancels(&events), vec![resting_a]);
+        assert_eq!(fills(events)[0].order_id, resting_b);
+        assert!(book.get_order(resting_a).is_none());
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1008);
+
+        // Cancel both: Bob's 2 fill, then both of Alice's orders are dropped
+        let resting_c = sell(&mut book, &mut vault, alice, 5);
+        let (taker_id, events) = buy(&mut book, &mut vault, 5, SelfTradePrevention::CancelBoth);
+        assert_eq!(cancels(&events), vec![resting_c, taker_id]);
+        assert_eq!(fills(events)[0].filled_qty, 2);
+        assert!(book.get_order(resting_c).is_none());
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert!(book.depth(PAIR, 1, now).unwrap().asks.is_empty());
+    }
+
+    #[test]
+    fn test_modify_order() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice bids 10 TokenA at price 10, three times
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let mut buy_order = book
+                .make_new_order(alice, PAIR, Side::Buy, 10, 10, now)
+                .unwrap();
+            vault.lock(alice, QUOTE, 100).unwrap();
+            buy_order.locked = 100;
+            ids.push(buy_order.id);
+            book.insert_new_order(buy_order).unwrap();
+        }
+
+        // Reducing the second bid keeps its place and unlocks 60 TokenB
+        book.modify_order(alice, ids[1], 10, 4, now + 1, &mut vault)
+            .unwrap();
+        assert_eq!(vault.get_locked(alice, QUOTE), 240);
+        assert_eq!(book.depth(PAIR, 1, now).unwrap().bids, vec![(10, 24)]);
+
+        // Bob sells 12 TokenA at price 10: the first bid fills, then the second
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 12, now + 2)
+            .unwrap();
+        vault.lock(bob, BASE, 12).unwrap();
+        sell_order.locked = 12;
+        let (_, events) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        let events = fills(events);
+        assert_eq!(events[0].order_id, ids[0]);
+        assert_eq!(events[2].order_id, ids[1]);
+        assert_eq!(events[2].filled_qty, 2);
+
+        // Moving the third bid to 11 for 5 TokenA unlocks 45 TokenB
+        book.modify_order(alice, ids[2], 11, 5, now + 3, &mut vault)
+            .unwrap();
+        let order = book.get_order(ids[2]).unwrap();
+        assert_eq!((order.price, order.qty, order.locked), (11, 5, 55));
+        assert_eq!(vault.get_locked(alice, QUOTE), 75);
+
+        // Bob asks 3 TokenA at price 12
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 12, 3, now + 4)
+            .unwrap();
+        vault.lock(bob, BASE, 3).unwrap();
+        sell_order.locked = 3;
+        let ask_id = sell_order.id;
+        book.insert_new_order(sell_order).unwrap();
+
+        // Raising the second bid to 12 for 5 TokenA locks 40 more and matches the ask
+        let (remaining, events) = book
+            .modify_order(alice, ids[1], 12, 5, now + 5, &mut vault)
+            .unwrap();
+        assert_eq!(fills(events)[0].order_id, ask_id);
+        assert_eq!(remaining.unwrap().qty, 2);
+        assert_eq!(
+            book.depth(PAIR, 5, now).unwrap().bids,
+            vec![(12, 2), (11, 5)]
+        );
+        assert_eq!(vault.get_locked(alice, QUOTE), 79);
+
+        // Only the owner can modify
+        assert!(matches!(
+            book.modify_order(bob, ids[2], 11, 1, now + 6, &mut vault),
+            Err(Error::NotOrderOwner)
+        ));
+
+        // An expired bid waiting for a sweep does not rest again
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 9, 10, now + 6)
+            .unwrap();
+        vault.lock(alice, QUOTE, 90).unwrap();
+        buy_order.locked = 90;
+        buy_order.expires_at = Some(now + 7);
+        let expiring_id = buy_order.id;
+        book.insert_new_order(buy_order).unwrap();
+        assert!(matches!(
+            book.modify_order(alice, expiring_id, 8, 10, now + 7, &mut vault),
+            Err(Error::ExpiryInPast)
+        ));
+    }
+
+    #[test]
+    fn test_cancel_all_orders() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice bids 10 TokenA at price 9 and 8, asks 10 at price 11 and 12
+        let mut ids = Vec::new();
+        for (side, price) in [
+            (Side::Buy, 9),
+            (Side::Sell, 11),
+            (Side::Buy, 8),
+            (Side::Sell, 12),
+        ] {
+            let mut order = book
+                .make_new_order(alice, PAIR, side, price, 10, now)
+                .unwrap();
+            let (token, locked) = match side {
+                Side::Buy => (QUOTE, price * 10),
+                Side::Sell => (BASE, 10),
+            };
+            vault.lock(alice, token, locked).unwrap();
+            order.locked = locked;
+            ids.push(order.id);
+            book.insert_new_order(order).unwrap();
+        }
+
+        // Nothing of Bob's to cancel
+        let cancelled = book.cancel_all_orders(bob, None, 10, &mut vault).unwrap();
+        assert!(cancelled.is_empty());
+
+        // Bids only, one at a time
+        let cancelled = book
+            .cancel_all_orders(alice, Some(Side::Buy), 1, &mut vault)
+            .unwrap();
+        assert_eq!(cancelled, vec![ids[0]]);
+        let open: Vec<u64> = book
+            .open_orders_of(alice, 0, 10)
+            .iter()
+            .map(|o| o.id)
+            .collect();
+        assert_eq!(open, vec![ids[1], ids[2], ids[3]]);
+        let cancelled = book
+            .cancel_all_orders(alice, Some(Side::Buy), 10, &mut vault)
+            .unwrap();
+        assert_eq!(cancelled, vec![ids[2]]);
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 1000);
+
+        // Then everything else
+        let cancelled = book.cancel_all_orders(alice, None, 10, &mut vault).unwrap();
+        assert_eq!(cancelled, vec![ids[1], ids[3]]);
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert!(book.open_orders_of(alice, 0, 10).is_empty());
+        assert_eq!(book.depth(PAIR, 5, now).unwrap(), Depth::default());
+    }
+
+    #[test]
+    fn test_counter_overflow() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // The last order id is handed out, then ids are exhausted
+        book.next_order_id = u64::MAX - 1;
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 5, now)
+            .unwrap();
+        assert_eq!(sell_order.id, u64::MAX - 1);
+        assert!(matches!(
+            book.make_new_order(bob, PAIR, Side::Sell, 10, 5, now),
+            Err(Error::Overflow)
+        ));
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        book.insert_new_order(sell_order).unwrap();
+
+        // Same for trade ids
+        book.next_order_id = 0;
+        book.next_trade_id = u64::MAX;
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 5, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 50).unwrap();
+        buy_order.locked = 50;
+        assert!(matches!(
+            book.match_sell_orders(buy_order, None, &mut vault),
+            Err(Error::Overflow)
+        ));
+    }
+
+    #[test]
+    fn test_zero_maker_price() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // An ask at price zero never passes placement, a taker must not divide by it
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 0, 5, now)
+            .unwrap();
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        book.insert_new_order(sell_order).unwrap();
+
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 5, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 50).unwrap();
+        buy_order.locked = 50;
+        assert!(matches!(
+            book.match_sell_orders(buy_order, None, &mut vault),
+            Err(Error::ZeroPrice)
+        ));
+    }
+
+    #[test]
+    fn test_client_order_id() {
+        let (mut book, _, alice, bob) = setup();
+
+        assert_eq!(book.order_id_of_client(alice, 7), None);
+        book.reserve_client_order_id(alice, 7, 0).unwrap();
+        assert_eq!(book.order_id_of_client(alice, 7), Some(0));
+
+        // unique per account
+        assert!(book.reserve_client_order_id(alice, 7, 1).is_err());
+        assert_eq!(book.order_id_of_client(alice, 7), Some(0));
+        book.reserve_client_order_id(bob, 7, 1).unwrap();
+        assert_eq!(book.order_id_of_client(bob, 7), Some(1));
+    }
+
+    #[test]
+    fn test_fill_or_kill_against_iceberg() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob sells 100 @ 10 showing 10 at a time
+        let mut iceberg = book
+            .make_new_order(bob, PAIR, Side::Sell, 10, 100, now)
+            .unwrap();
+        iceberg.display_qty = Some(10);
+        vault.lock(bob, BASE, 100).unwrap();
+        iceberg.locked = 100;
+        book.insert_new_order(iceberg).unwrap();
+
+        // A fill-or-kill buy of 50 counts the hidden slices, and fills in full
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 50, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 500).unwrap();
+        buy_order.locked = 500;
+        buy_order.time_in_force = TimeInForce::FillOrKill;
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 50);
+        let (res, evts) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        assert_eq!(fills(evts).len(), 10);
+        assert_eq!(vault.get_balance(alice, BASE), 1050);
+
+        // Slices re-queued behind an own order of the taker never reach it
+        let mut sell_order = book
+            .make_new_order(alice, PAIR, Side::Sell, 10, 5, now + 2)
+            .unwrap();
+        vault.lock(alice, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        book.insert_new_order(sell_order).unwrap();
+        let buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 30, now + 3)
+            .unwrap();
+        assert_eq!(book.fillable_qty(&buy_order).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_iceberg_order() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice sells 30 @ 10 showing 10 at a time, then 5 @ 10
+        let mut iceberg = book
+            .make_new_order(alice, PAIR, Side::Sell, 10, 30, now)
+            .unwrap();
+        iceberg.display_qty = Some(10);
+        vault.lock(alice, BASE, 30).unwrap();
+        iceberg.locked = 30;
+        let iceberg_id = iceberg.id;
+        book.insert_new_order(iceberg).unwrap();
+        let mut sell_order = book
+            .make_new_order(alice, PAIR, Side::Sell, 10, 5, now)
+            .unwrap();
+        vault.lock(alice, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        let sell_id = sell_order.id;
+        book.insert_new_order(sell_order).unwrap();
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().asks, vec![(10, 15)]);
+
+        // Bob buys 12: the slice fills and goes behind the 5
+        let mut buy_order = book
+            .make_new_order(bob, PAIR, Side::Buy, 10, 12, now + 1)
+            .unwrap();
+        vault.lock(bob, QUOTE, 120).unwrap();
+        buy_order.locked = 120;
+        let (res, evts) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        let filled: Vec<(u64, u128)> = fills(evts)
+            .iter()
+            .map(|e| (e.order_id, e.filled_qty))
+            .collect();
+        assert_eq!(filled[0], (iceberg_id, 10));
+        assert_eq!(filled[2], (sell_id, 2));
+        let order = book.get_order(iceberg_id).unwrap();
+        assert_eq!((order.qty, order.visible_qty(), order.locked), (20, 10, 20));
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().asks, vec![(10, 13)]);
+
+        // Bob buys 20: the 3 left, the second slice and 7 of the last one
+        let mut buy_order = book
+            .make_new_order(bob, PAIR, Side::Buy, 10, 20, now + 2)
+            .unwrap();
+        vault.lock(bob, QUOTE, 200).unwrap();
+        buy_order.locked = 200;
+        let (res, evts) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        let filled: Vec<(u64, u128)> = fills(evts)
+            .iter()
+            .map(|e| (e.order_id, e.filled_qty))
+            .collect();
+        assert_eq!(filled[0], (sell_id, 3));
+        assert_eq!(filled[2], (iceberg_id, 10));
+        assert_eq!(filled[4], (iceberg_id, 7));
+        assert!(book.get_order(sell_id).is_none());
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().asks, vec![(10, 3)]);
+        // the hidden remainder stayed locked all along
+        assert_eq!(vault.get_locked(alice, BASE), 3);
+        assert_eq!(vault.get_balance(bob, BASE), 1032);
+
+        // Alice bids 20 @ 9 showing 5, Bob sells exactly one slice
+        let mut iceberg = book
+            .make_new_order(alice, PAIR, Side::Buy, 9, 20, now)
+            .unwrap();
+        iceberg.display_qty = Some(5);
+        vault.lock(alice, QUOTE, 180).unwrap();
+        iceberg.locked = 180;
+        let iceberg_id = iceberg.id;
+        book.insert_new_order(iceberg).unwrap();
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 9, 5, now + 3)
+            .unwrap();
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        let (res, evts) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        assert_eq!(fills(evts).len(), 2);
+        let order = book.get_order(iceberg_id).unwrap();
+        assert_eq!((order.qty, order.locked), (15, 135));
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().bids, vec![(9, 5)]);
+
+        // Reducing it takes the hidden part first
+        book.modify_order(alice, iceberg_id, 9, 8, now + 4, &mut vault)
+            .unwrap();
+        let order = book.get_order(iceberg_id).unwrap();
+        assert_eq!((order.qty, order.visible_qty()), (8, 5));
+        book.modify_order(alice, iceberg_id, 9, 3, now + 4, &mut vault)
+            .unwrap();
+        assert_eq!(book.depth(PAIR, 5, now).unwrap().bids, vec![(9, 3)]);
+    }
+
+    #[test]
+    fn test_maker_price_execution() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice asks 10 @ 10, Bob buys 10 @ 12 and gets the ask's price
+        let mut sell_order = book
+            .make_new_order(alice, PAIR, Side::Sell, 10, 10, now)
+            .unwrap();
+        vault.lock(alice, BASE, 10).unwrap();
+        sell_order.locked = 10;
+        book.insert_new_order(sell_order).unwrap();
+
+        let mut buy_order = book
+            .make_new_order(bob, PAIR, Side::Buy, 12, 10, now + 1)
+            .unwrap();
+        vault.lock(bob, QUOTE, 120).unwrap();
+        buy_order.locked = 120;
+        let (res, evts) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        assert!(fills(evts).iter().all(|e| e.filled_price == 10));
+        assert_eq!(vault.get_balance(bob, QUOTE), 900); // 20 TokenB of improvement unlocked
+        assert_eq!(vault.get_locked(bob, QUOTE), 0);
+        assert_eq!(vault.get_balance(alice, QUOTE), 1100);
+
+        // Alice bids 10 @ 10, Bob sells 10 @ 8 and gets the bid's price
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 10, now + 2)
+            .unwrap();
+        vault.lock(alice, QUOTE, 100).unwrap();
+        buy_order.locked = 100;
+        book.insert_new_order(buy_order).unwrap();
+
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 8, 10, now + 3)
+            .unwrap();
+        vault.lock(bob, BASE, 10).unwrap();
+        sell_order.locked = 10;
+        let (res, evts) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        assert!(fills(evts).iter().all(|e| e.filled_price == 10));
+        assert_eq!(vault.get_balance(bob, QUOTE), 1000); // 20 TokenB more than asked
+        assert_eq!(vault.get_balance(alice, QUOTE), 1000);
+        assert_eq!(vault.get_locked(alice, QUOTE), 0);
+    }
+
+    // every locked token is backed by an open order of the account
+    fn assert_locks_backed(book: &BTreeOrderBook, vault: &Vault, pair: Pair, acct_id: AccountId) {
+        let (mut base, mut quote) = (0, 0);
+        let orders = book.open_orders_of(acct_id, 0, u32::MAX);
+        for order in orders.into_iter().filter(|order| order.pair == pair) {
+            match order.side {
+                Side::Buy => {
+                    assert_eq!(order.locked, order.price * order.qty);
+                    quote += order.locked;
+                }
+                Side::Sell => base += order.qty,
+            }
+        }
+        assert_eq!(vault.get_locked(acct_id, pair.0), base);
+        assert_eq!(vault.get_locked(acct_id, pair.1), quote);
+    }
+
+    #[test]
+    fn test_refund_price_improvement() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Bob asks 10 @ 8 and 10 @ 9
+        for price in [8, 9] {
+            let mut sell_order = book
+                .make_new_order(bob, PAIR, Side::Sell, price, 10, now)
+                .unwrap();
+            vault.lock(bob, BASE, 10).unwrap();
+            sell_order.locked = 10;
+            book.insert_new_order(sell_order).unwrap();
+        }
+
+        // Alice buys 30 @ 10: 20 fill below her price and 10 rest
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 30, now + 1)
+            .unwrap();
+        vault.lock(alice, QUOTE, 300).unwrap();
+        buy_order.locked = 300;
+        let (res, _) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        let buy_order = res.unwrap();
+        assert_eq!(buy_order.locked, 100);
+        book.insert_new_order(buy_order.clone()).unwrap();
+        // 170 spent, the 30 of improvement is back
+        assert_eq!(vault.get_balance(alice, QUOTE), 730);
+        assert_locks_backed(&book, &vault, PAIR, alice);
+        assert_locks_backed(&book, &vault, PAIR, bob);
+
+        // Bob asks 5 @ 7, Alice moves her bid up to 12 and takes it
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 7, 5, now + 2)
+            .unwrap();
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        book.insert_new_order(sell_order).unwrap();
+        book.modify_order(alice, buy_order.id, 12, 10, now + 3, &mut vault)
+            .unwrap();
+        assert_eq!(book.get_order(buy_order.id).unwrap().locked, 60);
+        assert_eq!(vault.get_balance(alice, QUOTE), 735);
+        assert_locks_backed(&book, &vault, PAIR, alice);
+        assert_locks_backed(&book, &vault, PAIR, bob);
+    }
+
+    #[test]
+    fn test_unique_ids_after_full_fill() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        let mut ids = Vec::new();
+        for i in 0..3 {
+            // Alice asks 10 @ 10
+            let mut sell_order = book
+                .make_new_order(alice, PAIR, Side::Sell, 10, 10, now + i)
+                .unwrap();
+            vault.lock(alice, BASE, 10).unwrap();
+            sell_order.locked = 10;
+            ids.push(sell_order.id);
+            book.insert_new_order(sell_order).unwrap();
+
+            // Bob takes all of it and never rests
+            let mut buy_order = book
+                .make_new_order(bob, PAIR, Side::Buy, 10, 10, now + i)
+                .unwrap();
+            vault.lock(bob, QUOTE, 100).unwrap();
+            buy_order.locked = 100;
+            ids.push(buy_order.id);
+            let (res, evts) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+            assert!(res.is_none());
+            assert_eq!(fills(evts)[1].order_id, *ids.last().unwrap());
+        }
+
+        // every order got its own id, in creation order
+        assert_eq!(ids, (0..6).collect::<Vec<u64>>());
+        let next = book
+            .make_new_order(alice, PAIR, Side::Sell, 10, 10, now)
+            .unwrap();
+        assert_eq!(next.id, 6);
+    }
+
+    #[test]
+    fn test_spread_after_fills() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+        assert_eq!(book.spread(PAIR).unwrap(), Spread::default());
+
+        // Alice asks 10 @ 10 and 10 @ 11, bids 10 @ 8 until 5 and 10 @ 7
+        for (side, price, expires_at) in [
+            (Side::Sell, 10, None),
+            (Side::Sell, 11, None),
+            (Side::Buy, 8, Some(5)),
+            (Side::Buy, 7, None),
+        ] {
+            let mut order = book
+                .make_new_order(alice, PAIR, side, price, 10, now)
+                .unwrap();
+            let (token, locked) = match side {
+                Side::Buy => (QUOTE, price * 10),
+                Side::Sell => (BASE, 10),
+            };
+            vault.lock(alice, token, locked).unwrap();
+            order.locked = locked;
+            order.expires_at = expires_at;
+            book.insert_new_order(order).unwrap();
+        }
+        let spread = book.spread(PAIR).unwrap();
+        assert_eq!(
+            (spread.best_bid, spread.best_ask, spread.spread),
+            (Some(8), Some(10), Some(2))
+        );
+
+        // Bob lifts both asks
+        let mut buy_order = book
+            .make_new_order(bob, PAIR, Side::Buy, 11, 20, now + 1)
+            .unwrap();
+        vault.lock(bob, QUOTE, 220).unwrap();
+        buy_order.locked = 220;
+        let (res, _) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        let spread = book.spread(PAIR).unwrap();
+        assert_eq!((spread.best_ask, spread.spread), (None, None));
+        assert_eq!(book.markets.get(PAIR).unwrap().min_sell_price, u128::MAX);
+
+        // Bob sells into the bids once the one @ 8 expired
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 7, 10, now + 5)
+            .unwrap();
+        vault.lock(bob, BASE, 10).unwrap();
+        sell_order.locked = 10;
+        let (res, _) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        assert_eq!(book.spread(PAIR).unwrap(), Spread::default());
+        assert_eq!(book.markets.get(PAIR).unwrap().max_buy_price, u128::MIN);
+
+        // A sell @ 8 now rests instead of matching the stale best bid
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 8, 10, now + 6)
+            .unwrap();
+        vault.lock(bob, BASE, 10).unwrap();
+        sell_order.locked = 10;
+        assert!(!book.would_cross(&sell_order).unwrap());
+        book.insert_new_order(sell_order).unwrap();
+        assert_eq!(book.spread(PAIR).unwrap().best_ask, Some(8));
+    }
+
+    #[test]
+    fn test_trade_events() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        // Alice asks 10 @ 10 and 10 @ 11
+        let mut ask_ids = vec![];
+        for price in [10, 11] {
+            let mut order = book
+                .make_new_order(alice, PAIR, Side::Sell, price, 10, now)
+                .unwrap();
+            vault.lock(alice, BASE, 10).unwrap();
+            order.locked = 10;
+            ask_ids.push(order.id);
+            book.insert_new_order(order).unwrap();
+        }
+
+        // Bob buys 15 @ 11: maker fill, taker fill and a trade per match
+        let mut buy_order = book
+            .make_new_order(bob, PAIR, Side::Buy, 11, 15, now + 1)
+            .unwrap();
+        vault.lock(bob, QUOTE, 165).unwrap();
+        buy_order.locked = 165;
+        let buy_id = buy_order.id;
+        let (res, evts) = book.match_sell_orders(buy_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        assert_eq!(evts.len(), 6);
+        let trades: Vec<EventTrade> = evts
+            .iter()
+            .filter_map(|e| match e {
+                BookEvent::Trade(t) => Some(*t),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].trade_id + 1, trades[1].trade_id);
+        for (trade, (maker_id, price, qty)) in trades
+            .iter()
+            .zip([(ask_ids[0], 10, 10), (ask_ids[1], 11, 5)])
+        {
+            assert_eq!(trade.pair, PAIR);
+            assert_eq!(
+                (trade.maker_order_id, trade.taker_order_id),
+                (maker_id, buy_id)
+            );
+            assert_eq!((trade.maker, trade.taker), (alice, bob));
+            assert_eq!(trade.taker_side, Side::Buy);
+            assert_eq!((trade.price, trade.qty), (price, qty));
+            assert_eq!(trade.quote_amount, price * qty);
+        }
+
+        let remaining: Vec<(u64, u128)> = fills(evts)
+            .iter()
+            .map(|e| (e.order_id, e.remaining_qty))
+            .collect();
+        assert_eq!(
+            remaining,
+            vec![(ask_ids[0], 0), (buy_id, 5), (ask_ids[1], 5), (buy_id, 0)]
+        );
+
+        // Bob sells 5 @ 11 into Alice's new bid: Alice is the maker
+        let mut bid = book
+            .make_new_order(alice, PAIR, Side::Buy, 11, 10, now + 2)
+            .unwrap();
+        vault.lock(alice, QUOTE, 110).unwrap();
+        bid.locked = 110;
+        let bid_id = bid.id;
+        book.insert_new_order(bid).unwrap();
+
+        let mut sell_order = book
+            .make_new_order(bob, PAIR, Side::Sell, 11, 5, now + 3)
+            .unwrap();
+        vault.lock(bob, BASE, 5).unwrap();
+        sell_order.locked = 5;
+        let (res, evts) = book.match_buy_orders(sell_order, None, &mut vault).unwrap();
+        assert!(res.is_none());
+        match evts.last() {
+            Some(BookEvent::Trade(trade)) => {
+                assert_eq!(trade.trade_id, trades[1].trade_id + 1);
+                assert_eq!(trade.maker_order_id, bid_id);
+                assert_eq!((trade.maker, trade.taker), (alice, bob));
+                assert_eq!(trade.taker_side, Side::Sell);
+                assert_eq!((trade.qty, trade.quote_amount), (5, 55));
+            }
+            e => panic!("unexpected event {:?}", e),
+        }
+        let remaining: Vec<u128> = fills(evts).iter().map(|e| e.remaining_qty).collect();
+        assert_eq!(remaining, vec![5, 0]);
+    }
+
+    #[test]
+    fn test_large_inputs_never_trap() {
+        let (mut book, mut vault, alice, bob) = setup();
+        let now = 1;
+
+        for seed in 0..20 {
+            let mut rng = Rng::new(seed);
+            // books share the test storage, a market per run keeps them apart
+            let pair = (
+                AccountId([0x80 + seed as u8; 32]),
+                AccountId([0xc0 + seed as u8; 32]),
+            );
+            book.add_market(pair).unwrap();
+            let maker_bps = (rng.next_u64() % 10_001) as u16;
+            book.set_fee_rates(maker_bps, (rng.next_u64() % 10_001) as u16)
+                .unwrap();
+
+            for step in 0..40 {
+                let owner = if rng.next_u64() % 2 == 0 { alice } else { bob };
+                let side = if rng.next_u64() % 2 == 0 {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                };
+                let (price, qty) = (rng.amount(), rng.amount());
+                let open = book.open_orders_of(owner, 0, u32::MAX);
+                let open: Vec<&Order> = open.iter().filter(|o| o.pair == pair).collect();
+
+                let res = match rng.next_u64() % 4 {
+                    0 if !open.is_empty() => {
+                        let order_id = open[rng.next_u64() as usize % open.len()].id;
+                        book.cancel_order(owner, order_id, &mut vault)
+                    }
+                    1 if !open.is_empty() => {
+                        let order_id = open[rng.next_u64() as usize % open.len()].id;
+                        let _ = vault.deposit(owner, pair.0, rng.amount());
+                        let _ = vault.deposit(owner, pair.1, rng.amount());
+                        book.modify_order(owner, order_id, price, qty, now, &mut vault)
+                            .map(|_| ())
+                    }
+                    _ => {
+                        // placement refuses a price * qty that does not fit
+                        let (token, required) = match side {
+                            Side::Buy => (pair.1, price.checked_mul(qty)),
+                            Side::Sell => (pair.0, Some(qty)),
+                        };
+                        let Some(required) = required else {
+                            continue;
+                        };
+                        let _ = vault.deposit(owner, token, required);
+                        if vault.lock(owner, token, required).is_err() {
+                            continue;
+                        }
+                        let mut order = book
+                            .make_new_order(owner, pair, side, price, qty, now)
+                            .unwrap();
+                        order.locked = required;
+                        if rng.next_u64() % 4 == 0 {
+                            order.display_qty = Some(rng.amount());
+                        }
+                        match side {
+                            Side::Buy => book.match_sell_orders(order, None, &mut vault),
+                            Side::Sell => book.match_buy_orders(order, None, &mut vault),
+                        }
+                        .and_then(|(res, _)| res.map_or(Ok(()), |o| book.insert_new_order(o)))
+                    }
+                };
+
+                // a failing call reverts the whole message, so the run ends here
+                match res {
+                    Ok(()) => {
+                        assert_locks_backed(&book, &vault, pair, alice);
+                        assert_locks_backed(&book, &vault, pair, bob);
+                    }
+                    Err(Error::Overflow | Error::InsufficientBalance(_)) => break,
+                    Err(e) => panic!("seed {seed} step {step}: {e:?}"),
+                }
+            }
+        }
+
+        // Amounts that cannot be priced are reported, not trapped on
+        let mut buy_order = book
+            .make_new_order(alice, PAIR, Side::Buy, 10, 10, now)
+            .unwrap();
+        vault.lock(alice, QUOTE, 100).unwrap();
+        buy_order.locked = 100;
+        let order_id = buy_order.id;
+        book.insert_new_order(buy_order).unwrap();
+        assert_eq!(
+            book.modify_order(alice, order_id, u128::MAX, 2, now, &mut vault)
+                .map(|_| ()),
+            Err(Error::Overflow)
+        );
+        assert_eq!(book.get_order(order_id).unwrap().price, 10);
+        book.set_fee_rates(0, 10_000).unwrap();
+        assert_eq!(book.fee(u128::MAX, true), 0);
+        assert_eq!(book.fee(u128::MAX, false), u128::MAX);
+        book.set_fee_rates(1, 1).unwrap();
+        assert_eq!(book.fee(u128::MAX, false), u128::MAX / 10_000);
     }
 }
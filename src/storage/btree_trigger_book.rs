@@ -0,0 +1,311 @@
+use ink::{prelude::collections::BTreeSet, primitives::AccountId, storage::Mapping};
+
+use super::queue::{Link, Queue};
+use crate::{
+    error::{Error, Result},
+    traits::{token_vault::TokenVault, trigger_book::TriggerBook},
+    types::{Pair, Side, StopOrder, Trigger},
+};
+
+/// Trigger prices of the waiting stop orders of a single trading pair.
+///
+/// Only prices live here, the stops waiting at a price are kept in
+/// `BTreeTriggerBook::queues` and `BTreeTriggerBook::links`, so firing a stop decodes
+/// prices, not every stop of the market.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Default)]
+struct TriggerPrices {
+    // prices of stops firing on a falling price, reached highest first
+    below: BTreeSet<u128>,
+
+    // prices of stops firing on a rising price, reached lowest first
+    above: BTreeSet<u128>,
+}
+
+impl TriggerPrices {
+    fn prices_mut(&mut self, trigger: Trigger) -> (&mut BTreeSet<u128>, u128) {
+        match trigger {
+            Trigger::AtOrBelow(price) => (&mut self.below, price),
+            Trigger::AtOrAbove(price) => (&mut self.above, price),
+        }
+    }
+
+    // the triggers of either direction reached first by `last_price`
+    fn first_reached(&self, last_price: u128) -> [Option<Trigger>; 2] {
+        let below = self
+            .below
+            .last()
+            .filter(|&&price| last_price <= price)
+            .map(|&price| Trigger::AtOrBelow(price));
+        let above = self
+            .above
+            .first()
+            .filter(|&&price| last_price >= price)
+            .map(|&price| Trigger::AtOrAbove(price));
+        [below, above]
+    }
+
+    fn is_empty(&self) -> bool {
+        self.below.is_empty() && self.above.is_empty()
+    }
+}
+
+// (pair, trigger)
+type TriggerKey = (Pair, Trigger);
+
+#[ink::storage_item]
+#[derive(Default)]
+pub struct BTreeTriggerBook {
+    // all waiting stop orders
+    stops: Mapping<u64, StopOrder>,
+
+    // waiting trigger prices per market
+    prices: Mapping<Pair, TriggerPrices>,
+
+    // triggers: (pair, trigger) -> FIFO queue of stop ids
+    queues: Mapping<TriggerKey, Queue>,
+
+    // stop id -> neighbours in its trigger queue
+    links: Mapping<u64, Link>,
+
+    // price of the latest trade per market
+    last_prices: Mapping<Pair, u128>,
+
+    // stop id generator
+    next_stop_id: u64,
+}
+
+impl BTreeTriggerBook {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // append a stop to the queue of its trigger
+    fn push_trigger(&mut self, key: TriggerKey, stop_id: u64) {
+        let queue = match self.queues.get(key) {
+            Some(mut queue) => {
+                queue.push_back(&mut self.links, stop_id);
+                queue
+            }
+            None => {
+                let (pair, trigger) = key;
+                let mut prices = self.prices.get(pair).unwrap_or_default();
+                let (set, price) = prices.prices_mut(trigger);
+                set.insert(price);
+                self.prices.insert(pair, &prices);
+                Queue::new(&mut self.links, stop_id)
+            }
+        };
+        self.queues.insert(key, &queue);
+    }
+
+    // drop a stop order and its trigger
+    fn remove_stop(&mut self, stop: &StopOrder) {
+        let key = (stop.pair, stop.trigger);
+        if let Some(mut queue) = self.queues.get(key) {
+            if queue.remove(&mut self.links, stop.id) {
+                self.queues.remove(key);
+                let mut prices = self.prices.get(stop.pair).unwrap_or_default();
+                let (set, price) = prices.prices_mut(stop.trigger);
+                set.remove(&price);
+                if prices.is_empty() {
+                    self.prices.remove(stop.pair);
+                } else {
+                    self.prices.insert(stop.pair, &prices);
+                }
+            } else {
+                self.queues.insert(key, &queue);
+            }
+        }
+        self.stops.remove(stop.id);
+    }
+}
+
+impl core::fmt::Debug for BTreeTriggerBook {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("BTreeTriggerBook").finish()
+    }
+}
+
+impl TriggerBook for BTreeTriggerBook {
+    fn insert_stop_order(&mut self, mut stop: StopOrder) -> Result<u64> {
+        if self
+            .last_price(stop.pair)
+            .is_some_and(|price| stop.trigger.is_met(price))
+        {
+            return Err(Error::StopWouldTrigger);
+        }
+
+        stop.id = self.next_stop_id;
+        let next_stop_id = stop.id.checked_add(1).ok_or(Error::Overflow)?;
+        self.push_trigger((stop.pair, stop.trigger), stop.id);
+        self.stops.insert(stop.id, &stop);
+        self.next_stop_id = next_stop_id;
+        Ok(stop.id)
+    }
+
+    fn set_last_price(&mut self, pair: Pair, price: u128) {
+        self.last_prices.insert(pair, &price);
+    }
+
+    fn last_price(&self, pair: Pair) -> Option<u128> {
+        self.last_prices.get(pair)
+    }
+
+    fn take_triggered(&mut self, pair: Pair) -> Option<StopOrder> {
+        let last_price = self.last_price(pair)?;
+        // oldest first between the two directions
+        let stop_id = self
+            .prices
+            .get(pair)?
+            .first_reached(last_price)
+            .into_iter()
+            .flatten()
+            .filter_map(|trigger| self.queues.get((pair, trigger)))
+            .map(|queue| queue.head)
+            .min()?;
+        let stop = self.stops.get(stop_id)?;
+        self.remove_stop(&stop);
+        Some(stop)
+    }
+
+    fn cancel_stop_order<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        stop_id: u64,
+        vault: &mut V,
+    ) -> Result<()> {
+        let stop = self
+            .stops
+            .get(stop_id)
+            .ok_or(Error::OrderNotFound(stop_id))?;
+        if stop.owner != acct_id {
+            return Err(Error::NotOrderOwner);
+        }
+
+        let (base, quote) = stop.pair;
+        let token = match stop.side {
+            Side::Buy => quote,
+            Side::Sell => base,
+        };
+        vault.unlock(stop.owner, token, stop.locked)?;
+        self.remove_stop(&stop);
+        Ok(())
+    }
+
+    fn get_stop_order(&self, stop_id: u64) -> Option<StopOrder> {
+        self.stops.get(stop_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::vault::Vault;
+    use crate::types::{StopKind, Token};
+    use ink::env::test;
+
+    const BASE: Token = AccountId([0xb; 32]);
+    const QUOTE: Token = AccountId([0xc; 32]);
+    const PAIR: Pair = (BASE, QUOTE);
+
+    fn setup() -> (BTreeTriggerBook, Vault, AccountId, AccountId) {
+        let book = BTreeTriggerBook::new();
+        let mut vault = Vault::default();
+        let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+        let alice = accounts.alice;
+        let bob = accounts.bob;
+
+        // make ink engine happy
+        test::set_callee::<ink::env::DefaultEnvironment>(accounts.charlie);
+
+        vault.deposit(alice, BASE, 1000).unwrap();
+        vault.deposit(alice, QUOTE, 1000).unwrap();
+
+        (book, vault, alice, bob)
+    }
+
+    fn stop(owner: AccountId, side: Side, trigger: Trigger, qty: u128) -> StopOrder {
+        StopOrder {
+            id: 0,
+            owner,
+            pair: PAIR,
+            side,
+            kind: StopKind::Limit,
+            trigger,
+            price: 10,
+            qty,
+            timestamp: 1,
+            locked: qty,
+        }
+    }
+
+    #[test]
+    fn test_trigger_order() {
+        let (mut book, _, alice, _) = setup();
+
+        // No trade yet, nothing triggers
+        let low = book
+            .insert_stop_order(stop(alice, Side::Sell, Trigger::AtOrBelow(8), 10))
+            .unwrap();
+        let high = book
+            .insert_stop_order(stop(alice, Side::Sell, Trigger::AtOrBelow(9), 10))
+            .unwrap();
+        let tie = book
+            .insert_stop_order(stop(alice, Side::Sell, Trigger::AtOrBelow(9), 10))
+            .unwrap();
+        let up = book
+            .insert_stop_order(stop(alice, Side::Buy, Trigger::AtOrAbove(12), 10))
+            .unwrap();
+        assert_eq!((low, high, tie, up), (0, 1, 2, 3));
+        assert!(book.take_triggered(PAIR).is_none());
+
+        // Between the triggers
+        book.set_last_price(PAIR, 10);
+        assert!(book.take_triggered(PAIR).is_none());
+
+        // A stop that is already met is rejected
+        assert!(book
+            .insert_stop_order(stop(alice, Side::Sell, Trigger::AtOrBelow(10), 10))
+            .is_err());
+
+        // Falling to 8 reaches 9 first, oldest first, then 8
+        book.set_last_price(PAIR, 8);
+        let ids: Vec<u64> = core::iter::from_fn(|| book.take_triggered(PAIR))
+            .map(|stop| stop.id)
+            .collect();
+        assert_eq!(ids, vec![high, tie, low]);
+        assert!(book.get_stop_order(low).is_none());
+
+        // Rising to 12
+        book.set_last_price(PAIR, 12);
+        assert_eq!(book.take_triggered(PAIR).unwrap().id, up);
+        assert!(book.take_triggered(PAIR).is_none());
+        assert_eq!(book.last_price(PAIR), Some(12));
+    }
+
+    #[test]
+    fn test_cancel_stop_order() {
+        let (mut book, mut vault, alice, bob) = setup();
+
+        vault.lock(alice, BASE, 10).unwrap();
+        let stop_id = book
+            .insert_stop_order(stop(alice, Side::Sell, Trigger::AtOrBelow(8), 10))
+            .unwrap();
+        assert_eq!(book.get_stop_order(stop_id).unwrap().qty, 10);
+
+        // Only the owner can cancel
+        assert!(book.cancel_stop_order(bob, stop_id, &mut vault).is_err());
+
+        book.cancel_stop_order(alice, stop_id, &mut vault).unwrap();
+        assert_eq!(vault.get_locked(alice, BASE), 0);
+        assert_eq!(vault.get_balance(alice, BASE), 1000);
+        assert!(book.get_stop_order(stop_id).is_none());
+
+        // Cancelled stops never trigger
+        book.set_last_price(PAIR, 1);
+        assert!(book.take_triggered(PAIR).is_none());
+        assert!(book.cancel_stop_order(alice, stop_id, &mut vault).is_err());
+    }
+}
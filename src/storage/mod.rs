@@ -1,5 +1,11 @@
 mod btree_order_book;
+mod btree_trigger_book;
+mod queue;
 mod vault;
 
+#[cfg(test)]
+mod testing;
+
 pub use btree_order_book::BTreeOrderBook;
+pub use btree_trigger_book::BTreeTriggerBook;
 pub use vault::Vault;
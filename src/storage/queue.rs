@@ -0,0 +1,128 @@
+use ink::storage::{traits::StorageKey, Mapping};
+
+/// Ends of a non-empty FIFO queue of ids, linked through a `Mapping<u64, Link>`.
+///
+/// Only the ends are stored with the owner of the queue, so pushing or removing an id
+/// touches a few small entries whatever the length of the queue.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Queue {
+    // oldest id
+    pub head: u64,
+    // newest id
+    pub tail: u64,
+}
+
+/// Neighbours of an id within its queue.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Default)]
+pub struct Link {
+    pub prev: Option<u64>,
+    pub next: Option<u64>,
+}
+
+impl Queue {
+    /// Starts a queue holding only `id`.
+    pub fn new<K: StorageKey>(links: &mut Mapping<u64, Link, K>, id: u64) -> Self {
+        links.insert(id, &Link::default());
+        Self { head: id, tail: id }
+    }
+
+    /// Appends `id` after the newest id.
+    pub fn push_back<K: StorageKey>(&mut self, links: &mut Mapping<u64, Link, K>, id: u64) {
+        let mut tail = links.get(self.tail).unwrap_or_default();
+        tail.next = Some(id);
+        links.insert(self.tail, &tail);
+        links.insert(
+            id,
+            &Link {
+                prev: Some(self.tail),
+                next: None,
+            },
+        );
+        self.tail = id;
+    }
+
+    /// Puts `id` before the oldest id.
+    pub fn push_front<K: StorageKey>(&mut self, links: &mut Mapping<u64, Link, K>, id: u64) {
+        let mut head = links.get(self.head).unwrap_or_default();
+        head.prev = Some(id);
+        links.insert(self.head, &head);
+        links.insert(
+            id,
+            &Link {
+                prev: None,
+                next: Some(self.head),
+            },
+        );
+        self.head = id;
+    }
+
+    /// Takes `id` out of the queue, returns whether the queue is empty now.
+    pub fn remove<K: StorageKey>(&mut self, links: &mut Mapping<u64, Link, K>, id: u64) -> bool {
+        let Link { prev, next } = links.take(id).unwrap_or_default();
+        if prev.is_none() && next.is_none() {
+            return true;
+        }
+
+        match prev {
+            Some(prev_id) => {
+                let mut link = links.get(prev_id).unwrap_or_default();
+                link.next = next;
+                links.insert(prev_id, &link);
+            }
+            None => self.head = next.unwrap_or(self.head),
+        }
+        match next {
+            Some(next_id) => {
+                let mut link = links.get(next_id).unwrap_or_default();
+                link.prev = prev;
+                links.insert(next_id, &link);
+            }
+            None => self.tail = prev.unwrap_or(self.tail),
+        }
+        false
+    }
+
+    /// The id queued after `id`, if any.
+    pub fn next<K: StorageKey>(links: &Mapping<u64, Link, K>, id: u64) -> Option<u64> {
+        links.get(id).and_then(|link| link.next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ink::env::test;
+
+    fn ids(links: &Mapping<u64, Link>, queue: Queue) -> Vec<u64> {
+        core::iter::successors(Some(queue.head), |&id| Queue::next(links, id)).collect()
+    }
+
+    #[test]
+    fn test_queue() {
+        // make ink engine happy
+        let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
+        test::set_callee::<ink::env::DefaultEnvironment>(accounts.charlie);
+
+        let mut links = Mapping::default();
+        let mut queue = Queue::new(&mut links, 1);
+        queue.push_back(&mut links, 2);
+        queue.push_back(&mut links, 3);
+        queue.push_front(&mut links, 0);
+        assert_eq!(ids(&links, queue), vec![0, 1, 2, 3]);
+
+        // middle, head and tail
+        assert!(!queue.remove(&mut links, 2));
+        assert!(!queue.remove(&mut links, 0));
+        assert!(!queue.remove(&mut links, 3));
+        assert_eq!(ids(&links, queue), vec![1]);
+        assert_eq!((queue.head, queue.tail), (1, 1));
+
+        // the last id empties the queue and leaves no links behind
+        assert!(queue.remove(&mut links, 1));
+        assert!((0..4).all(|id| !links.contains(id)));
+    }
+}
@@ -0,0 +1,32 @@
+//! Random inputs for property tests, seeded so a failing case can be replayed.
+
+/// A xorshift generator biased towards amounts near the integer limits.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        // spread nearby seeds apart, xorshift never leaves zero
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// A non-zero amount: full width, just below `u128::MAX`, of random magnitude or small.
+    pub(crate) fn amount(&mut self) -> u128 {
+        let wide = (u128::from(self.next_u64()) << 64) | u128::from(self.next_u64());
+        let amt = match self.next_u64() % 4 {
+            0 => wide,
+            1 => u128::MAX - wide % 1000,
+            2 => wide >> (self.next_u64() % 128),
+            _ => wide % 1000,
+        };
+        amt.max(1)
+    }
+}
@@ -18,6 +18,8 @@ pub(crate) struct Account {
 #[derive(Default)]
 pub struct Vault {
     accounts: Mapping<(AccountId, Token), Account>,
+    // accrued trading fees per token
+    fees: Mapping<Token, u128>,
 }
 
 impl core::fmt::Debug for Vault {
@@ -39,13 +41,18 @@ impl Vault {
     pub(crate) fn get_locked(&self, acct_id: AccountId, token: Token) -> u128 {
         self.get_or_default(acct_id, token).locked
     }
+
+    pub(crate) fn get_fees(&self, token: Token) -> u128 {
+        self.fees.get(token).unwrap_or_default()
+    }
 }
 
 impl TokenVault for Vault {
-    fn deposit(&mut self, acct_id: AccountId, token: Token, amt: u128) {
+    fn deposit(&mut self, acct_id: AccountId, token: Token, amt: u128) -> Result<()> {
         let mut acct = self.get_or_default(acct_id, token);
-        acct.balance = acct.balance.checked_add(amt).unwrap();
+        acct.balance = acct.balance.checked_add(amt).ok_or(Error::Overflow)?;
         self.accounts.insert((acct_id, token), &acct);
+        Ok(())
     }
 
     fn withdraw(&mut self, acct_id: AccountId, token: Token, amt: u128) -> Result<()> {
@@ -64,7 +71,7 @@ impl TokenVault for Vault {
             .balance
             .checked_sub(amt)
             .ok_or(Error::InsufficientBalance(token))?;
-        acct.locked = acct.locked.checked_add(amt).unwrap();
+        acct.locked = acct.locked.checked_add(amt).ok_or(Error::Overflow)?;
         self.accounts.insert((acct_id, token), &acct);
         Ok(())
     }
@@ -75,7 +82,7 @@ impl TokenVault for Vault {
             .locked
             .checked_sub(amt)
             .ok_or(Error::InsufficientLockedBalance(token))?;
-        acct.balance = acct.balance.checked_add(amt).unwrap();
+        acct.balance = acct.balance.checked_add(amt).ok_or(Error::Overflow)?;
         self.accounts.insert((acct_id, token), &acct);
         Ok(())
     }
@@ -86,22 +93,42 @@ impl TokenVault for Vault {
         to: AccountId,
         token: Token,
         amt: u128,
+        fee: u128,
     ) -> Result<()> {
         if from == to {
-            return Err(Error::InvalidTransfer(
-                "Cannot transfer locked to self".into(),
-            ));
+            return Err(Error::SelfTransfer);
         }
+        let received = amt.checked_sub(fee).ok_or(Error::FeeExceedsAmount)?;
         let mut from_acct = self.get_or_default(from, token);
         from_acct.locked = from_acct
             .locked
             .checked_sub(amt)
             .ok_or(Error::InsufficientLockedBalance(token))?;
-        self.accounts.insert((from, token), &from_acct);
-
         let mut to_acct = self.get_or_default(to, token);
-        to_acct.balance = to_acct.balance.checked_add(amt).unwrap();
+        to_acct.balance = to_acct
+            .balance
+            .checked_add(received)
+            .ok_or(Error::Overflow)?;
+        let fees = self
+            .get_fees(token)
+            .checked_add(fee)
+            .ok_or(Error::Overflow)?;
+
+        // nothing is written unless every amount fits
+        self.accounts.insert((from, token), &from_acct);
         self.accounts.insert((to, token), &to_acct);
+        if fee > 0 {
+            self.fees.insert(token, &fees);
+        }
+        Ok(())
+    }
+
+    fn withdraw_fees(&mut self, token: Token, amt: u128) -> Result<()> {
+        let fees = self
+            .get_fees(token)
+            .checked_sub(amt)
+            .ok_or(Error::InsufficientBalance(token))?;
+        self.fees.insert(token, &fees);
         Ok(())
     }
 }
@@ -109,8 +136,12 @@ impl TokenVault for Vault {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::testing::Rng;
     use ink::env::test;
 
+    const BASE: Token = AccountId([0xb; 32]);
+    const QUOTE: Token = AccountId([0xc; 32]);
+
     fn setup() -> (AccountId, AccountId) {
         let accounts = test::default_accounts::<ink::env::DefaultEnvironment>();
         // make ink engine happy
@@ -122,16 +153,16 @@ mod tests {
     fn test_deposit() {
         let (alice, _) = setup();
         let mut vault = Vault::default();
-        let token = Token::Base;
+        let token = BASE;
 
         // Test initial deposit
-        vault.deposit(alice, token, 100);
+        vault.deposit(alice, token, 100).unwrap();
         let account = vault.get_or_default(alice, token);
         assert_eq!(account.balance, 100);
         assert_eq!(account.locked, 0);
 
         // Test additional deposit
-        vault.deposit(alice, token, 50);
+        vault.deposit(alice, token, 50).unwrap();
         let account = vault.get_or_default(alice, token);
         assert_eq!(account.balance, 150);
     }
@@ -140,10 +171,10 @@ mod tests {
     fn test_withdraw() {
         let (alice, _) = setup();
         let mut vault = Vault::default();
-        let token = Token::Base;
+        let token = BASE;
 
         // Setup initial balance
-        vault.deposit(alice, token, 100);
+        vault.deposit(alice, token, 100).unwrap();
 
         // Test successful withdrawal
         assert!(vault.withdraw(alice, token, 50).is_ok());
@@ -161,10 +192,10 @@ mod tests {
     fn test_lock() {
         let (alice, _) = setup();
         let mut vault = Vault::default();
-        let token = Token::Base;
+        let token = BASE;
 
         // Setup initial balance
-        vault.deposit(alice, token, 100);
+        vault.deposit(alice, token, 100).unwrap();
 
         // Test successful lock
         assert!(vault.lock(alice, token, 50).is_ok());
@@ -183,10 +214,10 @@ mod tests {
     fn test_unlock() {
         let (alice, _) = setup();
         let mut vault = Vault::default();
-        let token = Token::Base;
+        let token = BASE;
 
         // Setup initial balance and locked amount
-        vault.deposit(alice, token, 100);
+        vault.deposit(alice, token, 100).unwrap();
         vault.lock(alice, token, 50).unwrap();
 
         // Test successful unlock
@@ -206,14 +237,14 @@ mod tests {
     fn test_transfer_locked() {
         let (alice, bob) = setup();
         let mut vault = Vault::default();
-        let token = Token::Base;
+        let token = BASE;
 
         // Setup initial balance and locked amount
-        vault.deposit(alice, token, 100);
+        vault.deposit(alice, token, 100).unwrap();
         vault.lock(alice, token, 50).unwrap();
 
         // Test successful transfer
-        assert!(vault.transfer_locked(alice, bob, token, 30).is_ok());
+        assert!(vault.transfer_locked(alice, bob, token, 30, 0).is_ok());
 
         let alice_account = vault.get_or_default(alice, token);
         assert_eq!(alice_account.balance, 50);
@@ -225,14 +256,14 @@ mod tests {
 
         // Test transfer with insufficient locked balance
         assert!(matches!(
-            vault.transfer_locked(alice, bob, token, 100),
+            vault.transfer_locked(alice, bob, token, 100, 0),
             Err(Error::InsufficientLockedBalance(_))
         ));
 
         // Test transfer to self - should fail
         assert!(matches!(
-            vault.transfer_locked(alice, alice, token, 10),
-            Err(Error::InvalidTransfer(_))
+            vault.transfer_locked(alice, alice, token, 10, 0),
+            Err(Error::SelfTransfer)
         ));
     }
 
@@ -240,12 +271,12 @@ mod tests {
     fn test_multiple_tokens() {
         let (alice, _) = setup();
         let mut vault = Vault::default();
-        let token1 = Token::Base;
-        let token2 = Token::Quote;
+        let token1 = BASE;
+        let token2 = QUOTE;
 
         // Test operations with different tokens
-        vault.deposit(alice, token1, 100);
-        vault.deposit(alice, token2, 200);
+        vault.deposit(alice, token1, 100).unwrap();
+        vault.deposit(alice, token2, 200).unwrap();
 
         let account1 = vault.get_or_default(alice, token1);
         assert_eq!(account1.balance, 100);
@@ -263,4 +294,125 @@ mod tests {
         assert_eq!(account2.balance, 200);
         assert_eq!(account2.locked, 0);
     }
+
+    #[test]
+    fn test_transfer_locked_fee() {
+        let (alice, bob) = setup();
+        let mut vault = Vault::default();
+        let token = QUOTE;
+
+        // Setup initial balance and locked amount
+        vault.deposit(alice, token, 100).unwrap();
+        vault.lock(alice, token, 100).unwrap();
+
+        // Bob receives 30 less a fee of 3
+        vault.transfer_locked(alice, bob, token, 30, 3).unwrap();
+        assert_eq!(vault.get_locked(alice, token), 70);
+        assert_eq!(vault.get_balance(bob, token), 27);
+        assert_eq!(vault.get_fees(token), 3);
+        assert_eq!(vault.get_fees(BASE), 0);
+
+        // Fee cannot exceed the amount
+        assert!(matches!(
+            vault.transfer_locked(alice, bob, token, 10, 11),
+            Err(Error::FeeExceedsAmount)
+        ));
+
+        // Withdraw accrued fees
+        assert!(matches!(
+            vault.withdraw_fees(token, 4),
+            Err(Error::InsufficientBalance(_))
+        ));
+        vault.withdraw_fees(token, 3).unwrap();
+        assert_eq!(vault.get_fees(token), 0);
+    }
+
+    #[test]
+    fn test_large_amounts_never_trap() {
+        let (alice, bob) = setup();
+
+        for seed in 0..20 {
+            let mut rng = Rng::new(seed);
+            let mut vault = Vault::default();
+            // vaults share the test storage, a token per run keeps them apart
+            let token = AccountId([0x80 + seed as u8; 32]);
+            // expected (balance, locked) of alice and bob, and the accrued fees
+            let mut model = [(0u128, 0u128); 2];
+            let mut fees = 0u128;
+
+            for _ in 0..50 {
+                let i = (rng.next_u64() % 2) as usize;
+                let (acct, other) = if i == 0 { (alice, bob) } else { (bob, alice) };
+                let amt = rng.amount();
+                let (balance, locked) = model[i];
+                let mut next = model;
+                let mut next_fees = fees;
+
+                // None when the operation must fail and leave everything as it was
+                let (res, expected) = match rng.next_u64() % 5 {
+                    0 => (
+                        vault.deposit(acct, token, amt),
+                        balance.checked_add(amt).map(|b| next[i].0 = b),
+                    ),
+                    1 => (
+                        vault.withdraw(acct, token, amt),
+                        balance.checked_sub(amt).map(|b| next[i].0 = b),
+                    ),
+                    2 => (
+                        vault.lock(acct, token, amt),
+                        balance
+                            .checked_sub(amt)
+                            .zip(locked.checked_add(amt))
+                            .map(|(b, l)| next[i] = (b, l)),
+                    ),
+                    3 => (
+                        vault.unlock(acct, token, amt),
+                        locked
+                            .checked_sub(amt)
+                            .zip(balance.checked_add(amt))
+                            .map(|(l, b)| next[i] = (b, l)),
+                    ),
+                    _ => {
+                        let fee = rng.amount() >> 8;
+                        (
+                            vault.transfer_locked(acct, other, token, amt, fee),
+                            amt.checked_sub(fee)
+                                .and_then(|received| model[1 - i].0.checked_add(received))
+                                .zip(locked.checked_sub(amt))
+                                .zip(fees.checked_add(fee))
+                                .map(|((b, l), f)| {
+                                    next[1 - i].0 = b;
+                                    next[i].1 = l;
+                                    next_fees = f;
+                                }),
+                        )
+                    }
+                };
+
+                match expected {
+                    Some(()) => {
+                        assert!(res.is_ok(), "seed {seed}: {res:?}");
+                        model = next;
+                        fees = next_fees;
+                    }
+                    None => assert!(res.is_err(), "seed {seed}"),
+                }
+                for (j, acct) in [alice, bob].into_iter().enumerate() {
+                    let state = (
+                        vault.get_balance(acct, token),
+                        vault.get_locked(acct, token),
+                    );
+                    assert_eq!(state, model[j], "seed {seed}");
+                }
+                assert_eq!(vault.get_fees(token), fees, "seed {seed}");
+            }
+        }
+
+        // The largest deposits are refused instead of wrapping
+        let mut vault = Vault::default();
+        let token = BASE;
+        vault.deposit(alice, token, u128::MAX).unwrap();
+        assert_eq!(vault.deposit(alice, token, 1), Err(Error::Overflow));
+        assert_eq!(vault.get_balance(alice, token), u128::MAX);
+    }
 }
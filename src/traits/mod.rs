@@ -1,2 +1,3 @@
 pub mod order_book;
 pub mod token_vault;
+pub mod trigger_book;
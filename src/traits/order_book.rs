@@ -2,7 +2,7 @@ use ink::{prelude::vec::Vec, primitives::AccountId};
 
 use crate::{
     error::Result,
-    types::{EventFilled, Order, Side, Token},
+    types::{BookEvent, Depth, Order, Pair, Side, Spread},
 };
 
 use super::token_vault::TokenVault;
@@ -13,61 +13,195 @@ use super::token_vault::TokenVault;
 /// and cancellation in a decentralized exchange. It implements price-time priority
 /// matching and handles both buy and sell orders.
 pub trait OrderBook {
+    /// Opens a new market for the given trading pair.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the market was created, Error if the pair is invalid or already listed
+    fn add_market(&mut self, pair: Pair) -> Result<()>;
+
+    /// Checks whether a market exists for the given trading pair.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    ///
+    /// # Returns
+    /// * `bool` - True if the market exists
+    fn has_market(&self, pair: Pair) -> bool;
+
+    /// Sets the fee rates charged on fills.
+    ///
+    /// Fees are taken from what each side receives: base for the buyer, quote for the seller.
+    ///
+    /// # Arguments
+    /// * `maker_fee_bps` - The fee rate of resting orders, in basis points
+    /// * `taker_fee_bps` - The fee rate of incoming orders, in basis points
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if set, Error if a rate exceeds 10000 basis points
+    fn set_fee_rates(&mut self, maker_fee_bps: u16, taker_fee_bps: u16) -> Result<()>;
+
+    /// Returns the (maker, taker) fee rates in basis points.
+    fn fee_rates(&self) -> (u16, u16);
+
     /// Creates a new order with the specified parameters.
     ///
+    /// The order ID is allocated here, so every order gets a unique ID whether it
+    /// rests, fills completely or is rejected.
+    ///
     /// # Arguments
     /// * `acct_id` - The account ID of the order creator
-    /// * `pair` - The trading pair (Base, Quote)
+    /// * `pair` - The trading pair (base, quote) of token contracts
     /// * `side` - The order side (Buy or Sell)
     /// * `price` - The order price
     /// * `qty` - The order quantity
     /// * `now` - The current timestamp
     ///
     /// # Returns
-    /// * `Order` - The newly created order
+    /// * `Result<Order>` - The newly created order, Error if order IDs are exhausted
     fn make_new_order(
-        &self,
+        &mut self,
         acct_id: AccountId,
-        pair: (Token, Token),
+        pair: Pair,
         side: Side,
         price: u128,
         qty: u128,
         now: u64,
-    ) -> Order;
+    ) -> Result<Order>;
 
     /// Inserts a new order into the order book.
     ///
     /// # Arguments
     /// * `order` - The order to insert
-    fn insert_new_order(&mut self, order: Order);
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if inserted, Error if the order's market does not exist
+    fn insert_new_order(&mut self, order: Order) -> Result<()>;
+
+    /// Keeps a new order that still crosses the book after its max fills, off the book.
+    ///
+    /// Pending orders are matched further by `match_pending_orders`, oldest first.
+    ///
+    /// # Arguments
+    /// * `order` - The unfilled remainder of the order
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if kept, Error if the order's market does not exist
+    fn park_order(&mut self, order: Order) -> Result<()>;
+
+    /// Binds a client order id of an account to an order, for good.
+    ///
+    /// # Arguments
+    /// * `acct_id` - The account ID of the order owner
+    /// * `client_order_id` - The id chosen by the account
+    /// * `order_id` - The ID of the order
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if reserved, Error if the account already used the client order id
+    fn reserve_client_order_id(
+        &mut self,
+        acct_id: AccountId,
+        client_order_id: u64,
+        order_id: u64,
+    ) -> Result<()>;
+
+    /// Continues matching the pending orders of a market.
+    ///
+    /// Each pending order matches as a taker arriving at `now`; what is left once it no longer
+    /// crosses rests on the book, or is unlocked and cancelled for an immediate-or-cancel
+    /// order. Each maker order consumed uses up one of `max_fills`, and so does a pending order
+    /// settled without consuming any, so a call processes at most `max_fills` of either.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    /// * `now` - The current timestamp
+    /// * `max_fills` - The maximum number of maker orders consumed and pending orders settled
+    /// * `vault` - The token vault for handling balance transfers
+    ///
+    /// # Returns
+    /// * `Result<(u32, Vec<BookEvent>)>` - The number of pending orders settled and fill/expiry events
+    fn match_pending_orders<V: TokenVault>(
+        &mut self,
+        pair: Pair,
+        now: u64,
+        max_fills: u32,
+        vault: &mut V,
+    ) -> Result<(u32, Vec<BookEvent>)>;
 
     /// Attempts to match a new buy order against existing sell orders.
     ///
+    /// Besides its quantity, the buy order is also capped by the quote tokens it has locked.
+    /// Expired sell orders met on the way are removed and their tokens unlocked.
+    /// Sell orders of the same owner are resolved by the buy order's self-trade prevention.
+    /// Quote locked beyond `price * qty` of the remainder, left by fills at better prices,
+    /// is unlocked.
+    ///
     /// # Arguments
     /// * `buy_order` - The buy order to match
+    /// * `max_fills` - The maximum number of maker orders to consume, `None` for no limit
     /// * `vault` - The token vault for handling balance transfers
     ///
     /// # Returns
-    /// * `Result<(Option<Order>, Vec<EventFilled>)>` - The remaining unfilled order (if any) and fill events
+    /// * `Result<(Option<Order>, Vec<BookEvent>)>` - The remaining unfilled order (if any) and fill/expiry events
     fn match_sell_orders<V: TokenVault>(
         &mut self,
         buy_order: Order,
+        max_fills: Option<u32>,
         vault: &mut V,
-    ) -> Result<(Option<Order>, Vec<EventFilled>)>;
+    ) -> Result<(Option<Order>, Vec<BookEvent>)>;
 
     /// Attempts to match a new sell order against existing buy orders.
     ///
+    /// Expired buy orders met on the way are removed and their tokens unlocked.
+    /// Buy orders of the same owner are resolved by the sell order's self-trade prevention.
+    ///
     /// # Arguments
     /// * `sell_order` - The sell order to match
+    /// * `max_fills` - The maximum number of maker orders to consume, `None` for no limit
     /// * `vault` - The token vault for handling balance transfers
     ///
     /// # Returns
-    /// * `Result<(Option<Order>, Vec<EventFilled>)>` - The remaining unfilled order (if any) and fill events
+    /// * `Result<(Option<Order>, Vec<BookEvent>)>` - The remaining unfilled order (if any) and fill/expiry events
     fn match_buy_orders<V: TokenVault>(
         &mut self,
         sell_order: Order,
+        max_fills: Option<u32>,
         vault: &mut V,
-    ) -> Result<(Option<Order>, Vec<EventFilled>)>;
+    ) -> Result<(Option<Order>, Vec<BookEvent>)>;
+
+    /// Checks whether an order would match any resting order if placed now.
+    ///
+    /// # Arguments
+    /// * `order` - The order to check
+    ///
+    /// # Returns
+    /// * `Result<bool>` - True if the order crosses the opposite side, Error if the market does not exist
+    fn would_cross(&self, order: &Order) -> Result<bool>;
+
+    /// Computes how much of an order could be filled immediately, up to its quantity.
+    ///
+    /// The hidden quantity of iceberg orders counts too, as their next slices fill within
+    /// the same level, but nothing past the first resting order of the same owner unless
+    /// self-trade prevention cancels it.
+    ///
+    /// # Arguments
+    /// * `order` - The order to check
+    ///
+    /// # Returns
+    /// * `Result<u128>` - The fillable quantity, Error if the market does not exist
+    fn fillable_qty(&self, order: &Order) -> Result<u128>;
+
+    /// Drops an order that will not rest on the book and unlocks its remaining tokens.
+    ///
+    /// # Arguments
+    /// * `order` - The unfilled remainder of the order
+    /// * `vault` - The token vault for handling balance unlocks
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if the remaining tokens are unlocked, Error if unlock fails
+    fn discard_order<V: TokenVault>(&mut self, order: Order, vault: &mut V) -> Result<()>;
 
     /// Cancels an existing order and unlocks any locked tokens.
     ///
@@ -84,4 +218,123 @@ pub trait OrderBook {
         order_id: u64,
         vault: &mut V,
     ) -> Result<()>;
+
+    /// Cancels the open orders of an account, oldest first, and unlocks their tokens.
+    ///
+    /// Orders are indexed per owner and side, so a call reads no more than `limit` orders
+    /// whatever the orders of the other side.
+    ///
+    /// # Arguments
+    /// * `acct_id` - The account ID of the order owner
+    /// * `side` - Only cancel orders of this side, `None` for both sides
+    /// * `limit` - The maximum number of orders to cancel
+    /// * `vault` - The token vault for handling balance unlocks
+    ///
+    /// # Returns
+    /// * `Result<Vec<u64>>` - The IDs of the cancelled orders
+    fn cancel_all_orders<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        side: Option<Side>,
+        limit: u32,
+        vault: &mut V,
+    ) -> Result<Vec<u64>>;
+
+    /// Changes the price or quantity of a resting order, locking or unlocking the difference.
+    ///
+    /// Reducing the quantity at the same price keeps the order's time priority. Any other
+    /// change moves the order to the back of its new price level, matching it first within
+    /// its max fills; an order stopped by them while still crossing waits for the crank.
+    /// A post-only order is rejected if its new price would match, and an expired order
+    /// that was not swept yet cannot be modified.
+    ///
+    /// # Arguments
+    /// * `acct_id` - The account ID of the order owner
+    /// * `order_id` - The ID of the order to modify
+    /// * `price` - The new price
+    /// * `qty` - The new unfilled quantity
+    /// * `now` - The current timestamp
+    /// * `vault` - The token vault for handling balance locks and transfers
+    ///
+    /// # Returns
+    /// * `Result<(Option<Order>, Vec<BookEvent>)>` - The order left open (if any) and fill/expiry events
+    fn modify_order<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        order_id: u64,
+        price: u128,
+        qty: u128,
+        now: u64,
+        vault: &mut V,
+    ) -> Result<(Option<Order>, Vec<BookEvent>)>;
+
+    /// Removes resting orders of a market that have expired, earliest expiry first.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    /// * `now` - The current timestamp
+    /// * `limit` - The maximum number of orders to remove
+    /// * `vault` - The token vault for handling balance unlocks
+    ///
+    /// # Returns
+    /// * `Result<Vec<BookEvent>>` - Expiry events of the removed orders, Error if the market does not exist
+    fn remove_expired_orders<V: TokenVault>(
+        &mut self,
+        pair: Pair,
+        now: u64,
+        limit: u32,
+        vault: &mut V,
+    ) -> Result<Vec<BookEvent>>;
+
+    /// Returns a resting order by its ID.
+    ///
+    /// # Arguments
+    /// * `order_id` - The ID of the order
+    ///
+    /// # Returns
+    /// * `Option<Order>` - The order if it is still on the book
+    fn get_order(&self, order_id: u64) -> Option<Order>;
+
+    /// Looks up the order placed by an account under a client order id.
+    ///
+    /// # Arguments
+    /// * `acct_id` - The account ID of the order owner
+    /// * `client_order_id` - The id chosen by the account
+    ///
+    /// # Returns
+    /// * `Option<u64>` - The ID of the order, None if the client order id was never used
+    fn order_id_of_client(&self, acct_id: AccountId, client_order_id: u64) -> Option<u64>;
+
+    /// Returns a page of the resting orders of an account, in ascending ID order.
+    ///
+    /// # Arguments
+    /// * `acct_id` - The account ID of the order owner
+    /// * `offset` - The number of orders to skip
+    /// * `limit` - The maximum number of orders to return
+    ///
+    /// # Returns
+    /// * `Vec<Order>` - The open orders of the account
+    fn open_orders_of(&self, acct_id: AccountId, offset: u32, limit: u32) -> Vec<Order>;
+
+    /// Returns the best resting prices of a market and the gap between them.
+    ///
+    /// Reads the book's shortcuts only, so expired orders not yet removed are included.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    ///
+    /// # Returns
+    /// * `Result<Spread>` - Best bid/ask and spread, Error if the market does not exist
+    fn spread(&self, pair: Pair) -> Result<Spread>;
+
+    /// Aggregates the resting quantity of the best price levels on each side of a market.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    /// * `levels` - The maximum number of price levels per side
+    /// * `now` - The current timestamp, expired orders are left out
+    ///
+    /// # Returns
+    /// * `Result<Depth>` - Best bid/ask and the aggregated levels, Error if the market does not exist
+    fn depth(&self, pair: Pair, levels: u32, now: u64) -> Result<Depth>;
 }
@@ -12,15 +12,18 @@ pub trait TokenVault {
     ///
     /// # Arguments
     /// * `acct_id` - The account ID to deposit tokens to
-    /// * `token` - The type of token to deposit (Base or Quote)
+    /// * `token` - The token (contract address) to deposit
     /// * `amt` - The amount of tokens to deposit
-    fn deposit(&mut self, acct_id: AccountId, token: Token, amt: u128);
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if deposit successful, Error if the balance would overflow
+    fn deposit(&mut self, acct_id: AccountId, token: Token, amt: u128) -> Result<()>;
 
     /// Withdraws tokens from an account's balance.
     ///
     /// # Arguments
     /// * `acct_id` - The account ID to withdraw tokens from
-    /// * `token` - The type of token to withdraw (Base or Quote)
+    /// * `token` - The token (contract address) to withdraw
     /// * `amt` - The amount of tokens to withdraw
     ///
     /// # Returns
@@ -31,7 +34,7 @@ pub trait TokenVault {
     ///
     /// # Arguments
     /// * `acct_id` - The account ID to lock tokens from
-    /// * `token` - The type of token to lock (Base or Quote)
+    /// * `token` - The token (contract address) to lock
     /// * `amt` - The amount of tokens to lock
     ///
     /// # Returns
@@ -42,28 +45,40 @@ pub trait TokenVault {
     ///
     /// # Arguments
     /// * `acct_id` - The account ID to unlock tokens for
-    /// * `token` - The type of token to unlock (Base or Quote)
+    /// * `token` - The token (contract address) to unlock
     /// * `amt` - The amount of tokens to unlock
     ///
     /// # Returns
     /// * `Result<()>` - Ok if unlock successful, Error if insufficient locked balance
     fn unlock(&mut self, acct_id: AccountId, token: Token, amt: u128) -> Result<()>;
 
-    /// Transfers locked tokens between accounts to fill an order.
+    /// Transfers locked tokens between accounts to fill an order, keeping a fee.
     ///
     /// # Arguments
     /// * `from` - The account ID to transfer tokens from
     /// * `to` - The account ID to transfer tokens to
-    /// * `token` - The type of token to transfer (Base or Quote)
+    /// * `token` - The token (contract address) to transfer
     /// * `amt` - The amount of tokens to transfer
+    /// * `fee` - The part of `amt` accrued to the fee pool instead of `to`
     ///
     /// # Returns
-    /// * `Result<()>` - Ok if transfer successful, Error if transfer fails
+    /// * `Result<()>` - Ok if transfer successful, Error if transfer fails or the fee exceeds the amount
     fn transfer_locked(
         &mut self,
         from: AccountId,
         to: AccountId,
         token: Token,
         amt: u128,
+        fee: u128,
     ) -> Result<()>;
+
+    /// Withdraws tokens from the accrued fee pool.
+    ///
+    /// # Arguments
+    /// * `token` - The token (contract address) to withdraw
+    /// * `amt` - The amount of tokens to withdraw
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if withdrawal successful, Error if insufficient fees
+    fn withdraw_fees(&mut self, token: Token, amt: u128) -> Result<()>;
 }
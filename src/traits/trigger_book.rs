@@ -0,0 +1,76 @@
+use ink::primitives::AccountId;
+
+use crate::{
+    error::Result,
+    types::{Pair, StopOrder},
+};
+
+use super::token_vault::TokenVault;
+
+/// A trait for keeping stop orders off the order book until they trigger.
+///
+/// This trait tracks the last trade price of each market and hands out the stop
+/// orders whose trigger it reaches, so they can be matched as regular orders. Funds
+/// of a stop order stay locked in the vault while it waits.
+pub trait TriggerBook {
+    /// Stores a new stop order under the next stop ID.
+    ///
+    /// # Arguments
+    /// * `stop` - The stop order with its funds already locked, its `id` is assigned here
+    ///
+    /// # Returns
+    /// * `Result<u64>` - The ID of the stop order, Error if the last trade price already meets its trigger
+    fn insert_stop_order(&mut self, stop: StopOrder) -> Result<u64>;
+
+    /// Records the price of the latest trade of a market.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    /// * `price` - The price of the trade
+    fn set_last_price(&mut self, pair: Pair, price: u128);
+
+    /// Returns the price of the latest trade of a market.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    ///
+    /// # Returns
+    /// * `Option<u128>` - The last trade price, None if the market never traded
+    fn last_price(&self, pair: Pair) -> Option<u128>;
+
+    /// Removes and returns a stop order of a market triggered by its last trade price.
+    ///
+    /// Stops are handed out in the order the price reaches them, oldest first on ties.
+    ///
+    /// # Arguments
+    /// * `pair` - The trading pair (base, quote) of token contracts
+    ///
+    /// # Returns
+    /// * `Option<StopOrder>` - A triggered stop order, None if no stop is triggered
+    fn take_triggered(&mut self, pair: Pair) -> Option<StopOrder>;
+
+    /// Cancels a waiting stop order and unlocks its tokens.
+    ///
+    /// # Arguments
+    /// * `acct_id` - The account ID of the stop order owner
+    /// * `stop_id` - The ID of the stop order to cancel
+    /// * `vault` - The token vault for handling balance unlocks
+    ///
+    /// # Returns
+    /// * `Result<()>` - Ok if cancelled, Error if the stop order is not found or not owned
+    fn cancel_stop_order<V: TokenVault>(
+        &mut self,
+        acct_id: AccountId,
+        stop_id: u64,
+        vault: &mut V,
+    ) -> Result<()>;
+
+    /// Returns a waiting stop order by its ID.
+    ///
+    /// # Arguments
+    /// * `stop_id` - The ID of the stop order
+    ///
+    /// # Returns
+    /// * `Option<StopOrder>` - The stop order if it has not triggered or been cancelled
+    fn get_stop_order(&self, stop_id: u64) -> Option<StopOrder>;
+}
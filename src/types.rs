@@ -1,4 +1,4 @@
-use ink::primitives::AccountId;
+use ink::{prelude::vec::Vec, primitives::AccountId};
 
 #[ink::scale_derive(Encode, Decode, TypeInfo)]
 #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
@@ -8,41 +8,313 @@ pub enum Side {
     Sell,
 }
 
+impl Side {
+    /// The side resting orders must be on to match this side.
+    pub fn opposite(self) -> Self {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
 #[ink::scale_derive(Encode, Decode, TypeInfo)]
 #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Order {
     pub id: u64,
     pub owner: AccountId,
-    pub pair: (Token, Token),
+    pub pair: Pair,
     pub side: Side,
     pub price: u128,
     pub qty: u128,
     pub timestamp: u64,
     pub locked: u128,
+    pub expires_at: Option<u64>,
+    /// How long the order stays active, only good-till-cancel and post-only orders rest.
+    pub time_in_force: TimeInForce,
+    /// Maximum number of maker orders to match at a time, `None` for no limit.
+    pub max_fills: Option<u32>,
+    pub stp: SelfTradePrevention,
+    pub client_order_id: Option<u64>,
+    /// Slice size of an iceberg order, `None` to show the whole qty.
+    pub display_qty: Option<u128>,
+    /// Part of `qty` not shown on the book yet.
+    pub hidden_qty: u128,
+}
+
+impl Order {
+    /// Whether a good-till-time order has expired at `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+
+    /// The part of `qty` shown on the book.
+    pub fn visible_qty(&self) -> u128 {
+        self.qty.saturating_sub(self.hidden_qty)
+    }
+
+    /// Hides all but the first slice of an iceberg order about to rest.
+    pub fn show_first_slice(&mut self) {
+        self.hidden_qty = self
+            .display_qty
+            .map_or(0, |display_qty| self.qty.saturating_sub(display_qty));
+    }
+
+    /// Reveals the next slice of an iceberg order once the visible one is used up.
+    pub fn show_next_slice(&mut self) {
+        let slice = self.display_qty.unwrap_or(self.hidden_qty);
+        self.hidden_qty = self.hidden_qty.saturating_sub(slice);
+    }
+}
+
+/// How long an order stays active on the book.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimeInForce {
+    /// Rest the unfilled remainder until cancelled.
+    #[default]
+    GoodTillCancel,
+    /// Match what is possible immediately, cancel the remainder.
+    ImmediateOrCancel,
+    /// Fill the whole quantity immediately or reject the order.
+    FillOrKill,
+    /// Only rest on the book, reject the order if it would match.
+    PostOnly,
+}
+
+/// How to resolve a match between two orders of the same account.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    /// Cancel the unfilled remainder of the incoming order.
+    #[default]
+    CancelNewest,
+    /// Cancel the resting order and keep matching.
+    CancelOldest,
+    /// Cancel both orders.
+    CancelBoth,
+    /// Reduce both orders by the smaller quantity, cancelling whichever reaches zero.
+    Decrement,
 }
 
+/// Optional flags of a limit order.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OrderOptions {
+    pub time_in_force: TimeInForce,
+    /// Block timestamp from which a resting order is expired, `None` for no expiry.
+    pub expires_at: Option<u64>,
+    /// Maximum number of maker orders to match on placement, `None` for no limit.
+    pub max_fills: Option<u32>,
+    /// What to do when the order would match a resting order of the same account.
+    pub stp: SelfTradePrevention,
+    /// Caller-chosen id, unique per account and never reusable, `None` for no id.
+    pub client_order_id: Option<u64>,
+    /// Quantity shown on the book at a time, making an iceberg order, `None` to show it all.
+    pub display_qty: Option<u128>,
+}
+
+/// A single operation of a batch.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    /// Place a limit order.
+    Place {
+        pair: Pair,
+        side: Side,
+        price: u128,
+        qty: u128,
+        options: OrderOptions,
+    },
+    /// Cancel an open order.
+    Cancel { order_id: u64 },
+}
+
+/// The last trade price condition that fires a stop order.
 #[ink::scale_derive(Encode, Decode, TypeInfo)]
 #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Token {
-    Base,
-    Quote,
+pub enum Trigger {
+    /// Fires once the last trade price falls to or below the price, e.g. a stop-loss sell.
+    AtOrBelow(u128),
+    /// Fires once the last trade price rises to or above the price, e.g. a take-profit sell.
+    AtOrAbove(u128),
 }
 
-#[derive(Debug)]
+impl Trigger {
+    /// Whether a trade at `last_price` fires the trigger.
+    pub fn is_met(self, last_price: u128) -> bool {
+        match self {
+            Trigger::AtOrBelow(price) => last_price <= price,
+            Trigger::AtOrAbove(price) => last_price >= price,
+        }
+    }
+}
+
+/// What a stop order turns into once triggered.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopKind {
+    /// A good-till-cancel limit order at the stop's price.
+    Limit,
+    /// A market order no worse than the stop's price, the unfilled remainder is unlocked.
+    Market,
+}
+
+/// An order waiting off the book until the last trade price reaches its trigger.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StopOrder {
+    /// The unique identifier of the stop order, separate from order IDs.
+    pub id: u64,
+    pub owner: AccountId,
+    pub pair: Pair,
+    pub side: Side,
+    pub kind: StopKind,
+    pub trigger: Trigger,
+    /// The limit price of a stop-limit order, the worst price of a stop-market order.
+    pub price: u128,
+    pub qty: u128,
+    pub timestamp: u64,
+    /// Funds locked at placement, handed over to the order once triggered.
+    pub locked: u128,
+}
+
+/// A token is identified by the address of its ERC20 contract.
+pub type Token = AccountId;
+
+/// A trading pair of (base, quote) tokens.
+pub type Pair = (Token, Token);
+
+/// Basis points in 100%, the denominator of fee rates.
+pub const BPS_DENOMINATOR: u128 = 10_000;
+
+/// The size of a market order, also how events report what an order is sized by.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketAmount {
+    /// Quantity of base tokens to buy or sell.
+    Base(u128),
+    /// Amount of quote tokens to spend, buys only.
+    Quote(u128),
+}
+
+/// The outcome of a market order.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarketFill {
+    /// The unique identifier of the order.
+    pub order_id: u64,
+    /// The base quantity filled.
+    pub filled_qty: u128,
+    /// The quote amount paid or received.
+    pub quote_amount: u128,
+    /// The average fill price, rounded down.
+    pub avg_price: u128,
+    /// The fee paid, in base for buys and in quote for sells.
+    pub fee: u128,
+}
+
+/// The best prices of a market.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Spread {
+    /// The highest buy price, if any.
+    pub best_bid: Option<u128>,
+    /// The lowest sell price, if any.
+    pub best_ask: Option<u128>,
+    /// Best ask minus best bid, if both sides have resting orders.
+    pub spread: Option<u128>,
+}
+
+/// A snapshot of the top levels of a market.
+#[ink::scale_derive(Encode, Decode, TypeInfo)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Depth {
+    /// The highest buy price, if any.
+    pub best_bid: Option<u128>,
+    /// The lowest sell price, if any.
+    pub best_ask: Option<u128>,
+    /// Buy levels as (price, total qty), best price first.
+    pub bids: Vec<(u128, u128)>,
+    /// Sell levels as (price, total qty), best price first.
+    pub asks: Vec<(u128, u128)>,
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct EventFilled {
     pub order_id: u64,
     pub filled_price: u128,
     pub filled_qty: u128,
+    pub remaining_qty: u128,
+    pub fee: u128,
 }
 
 impl EventFilled {
-    pub fn new(order_id: u64, filled_price: u128, filled_qty: u128) -> Self {
+    pub fn new(
+        order_id: u64,
+        filled_price: u128,
+        filled_qty: u128,
+        remaining_qty: u128,
+        fee: u128,
+    ) -> Self {
         Self {
             order_id,
             filled_price,
             filled_qty,
+            remaining_qty,
+            fee,
         }
     }
 }
+
+/// A single match between an incoming taker and a resting maker order.
+#[derive(Debug, Clone, Copy)]
+pub struct EventTrade {
+    pub trade_id: u64,
+    pub pair: Pair,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub maker: AccountId,
+    pub taker: AccountId,
+    pub taker_side: Side,
+    pub price: u128,
+    pub qty: u128,
+    pub quote_amount: u128,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EventCancelled {
+    pub order_id: u64,
+}
+
+impl EventCancelled {
+    pub fn new(order_id: u64) -> Self {
+        Self { order_id }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EventExpired {
+    pub order_id: u64,
+}
+
+impl EventExpired {
+    pub fn new(order_id: u64) -> Self {
+        Self { order_id }
+    }
+}
+
+/// Events produced by the order book while processing orders.
+#[derive(Debug, Clone, Copy)]
+pub enum BookEvent {
+    Filled(EventFilled),
+    Expired(EventExpired),
+    Cancelled(EventCancelled),
+    Trade(EventTrade),
+}